@@ -1,3 +1,557 @@
+use connect4::agent::{Agent, SeededAI, SharedAgent};
+use connect4::ai::AI;
+use connect4::book::OpeningBook;
+use connect4::difficulty::Difficulty;
+use connect4::engine::ExternalEngine;
+use connect4::eval::EvalWeights;
+use connect4::game::{Board, BoardSize, FirstPlayer, GameConfig, Variant};
+use connect4::layout::Layout;
+use connect4::mcts::Mcts;
+use connect4::player::{PlayerId, PlayerType};
+use connect4::protocol::{board_from_moves, ProtocolError};
+use connect4::series::{Match, MatchLength};
+use connect4::spectator::Broadcaster;
+use connect4::sprt::{balanced_openings, Sprt, SprtConfig};
+use connect4::tournament::{Entrant, Format, Tournament};
+use connect4::tune::{load_positions, save_positions, self_play_positions, Tuner};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::env;
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage:
+    connect4_CLI [play [--ai LEVEL] [--ponder] [--popout | --popten | --cylinder | --elimination
+                      | --powerups | --free]
+                      [--players N] [--size COLSxROWS[:N]] [--layout FILE] [--seed N]
+                      [--first 1|2|random|keep] [--order N,N,...]]
+    connect4_CLI match [--best-of N | --first-to K] [--seed N] PLAYER PLAYER
+    connect4_CLI tournament [--gauntlet] [--games N] [--threads N] [--movetime MS]
+                            [--seed N] ENTRANT...
+    connect4_CLI sprt [--elo0 E] [--elo1 E] [--alpha A] [--beta B] [--max-games N]
+                      [--openings PLIES] [--random-plies N] [--seed N] [--threads N]
+                      [--movetime MS] CANDIDATE BASELINE
+    connect4_CLI book [--plies N] [--depth N] OUTPUT
+    connect4_CLI tune [--games N] [--depth N] [--random-plies N] [--passes N]
+                      [--start WEIGHTS] [--positions FILE] [--seed N] OUTPUT
+    connect4_CLI bench [--depth N] [--threads N]
+
+Entrants are either `depth:N` for our AI searching N plies, `depth:N:WEIGHTS`
+to also load its evaluation weights from a file, `level:LEVEL` for one of the
+difficulty levels, `mcts:N` for Monte Carlo tree search with N random
+playouts a move, or `engine:PATH` for an external engine speaking the
+connect4_engine protocol.
+
+In play, --players 3 or 4 adds more players, all played by the AI with
+--ai, --order sets the order they take turns in, e.g. 3,1,2 for Player 3
+first, and --size changes the board and how many in a row win, e.g. 9x7:5.
+--layout starts from a drawing of the board with discs already played and
+cells marked * blocked for the whole game, which also sets the board size.
+A `to-move O` line in the file says who plays first. --first picks who
+does instead: 1 (or one), 2 (or two), random, or keep, the default, for
+Player 1 unless the layout says otherwise.
+--free drops gravity, so moves name a column and a row like 3,2; with
+--size 3x3:3 that is tic-tac-toe.
+
+Match players are `human`, `level:LEVEL` or `engine:PATH`. Levels are beginner, easy,
+medium, hard, expert and master.
+
+With --seed every random choice is made from the seed, so the same command
+plays the same games again. A weights file with
+`mode = threats` makes the AI weigh odd and even row threats as well.";
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(2);
+}
+
+fn parse_number<T: std::str::FromStr>(value: Option<&String>, flag: &str) -> T {
+    match value.map(|value| value.parse()) {
+        Some(Ok(number)) => number,
+        _ => fail(&format!("{} needs a number", flag)),
+    }
+}
+
+fn parse_level(level: &str) -> Difficulty {
+    level
+        .parse()
+        .unwrap_or_else(|err: ProtocolError| fail(&err.0))
+}
+
+/// Each game the entrant plays takes its random choices from the seed it is given.
+fn ai_entrant(spec: &str, ai: AI) -> Entrant {
+    Entrant::new(spec, move |seed| {
+        Ok(Box::new(SeededAI::new(ai.clone(), seed)) as Box<dyn Agent>)
+    })
+}
+
+fn parse_entrant(spec: &str, movetime: Duration) -> Entrant {
+    if let Some(ai_spec) = spec.strip_prefix("depth:") {
+        let (depth, weights) = match ai_spec.split_once(':') {
+            Some((depth, path)) => match EvalWeights::load(path) {
+                Ok(weights) => (depth, weights),
+                Err(err) => fail(&format!("could not load weights from {}: {}", path, err)),
+            },
+            None => (ai_spec, EvalWeights::default()),
+        };
+        let ai = AI {
+            depth: parse_number(Some(&depth.to_string()), "depth:"),
+            weights,
+            ..AI::default()
+        };
+        return ai_entrant(spec, ai);
+    }
+    if let Some(level) = spec.strip_prefix("level:") {
+        return ai_entrant(spec, parse_level(level).ai());
+    }
+    if let Some(playouts) = spec.strip_prefix("mcts:") {
+        let playouts: usize = parse_number(Some(&playouts.to_string()), "mcts:");
+        return Entrant::new(spec, move |seed| {
+            Ok(Box::new(Mcts::new(playouts, seed)) as Box<dyn Agent>)
+        });
+    }
+    if let Some(path) = spec.strip_prefix("engine:") {
+        let path = path.to_string();
+        return Entrant::new(path.clone(), move |_| {
+            let engine = ExternalEngine::spawn(path.as_str(), &[] as &[&str])?;
+            let engine = engine.with_movetime(movetime);
+            Ok(Box::new(engine) as Box<dyn Agent>)
+        });
+    }
+    fail(&format!("unknown entrant: {}", spec))
+}
+
+/// A human against nobody or against the AI at `--ai LEVEL`.
+fn play(args: &[String]) {
+    let mut ai = None;
+    let mut ponder = false;
+    let mut players = 2;
+    let mut size = BoardSize::default();
+    let mut layout = None;
+    let mut order: Option<Vec<PlayerId>> = None;
+    let mut variant = Variant::Classic;
+    let mut config = GameConfig::random();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ai" => match args.next() {
+                Some(level) => ai = Some(parse_level(level).ai()),
+                None => fail("--ai needs a level"),
+            },
+            "--ponder" => ponder = true,
+            "--popout" => variant = Variant::PopOut,
+            "--popten" => variant = Variant::PopTen,
+            "--cylinder" => variant = Variant::Cylinder,
+            "--elimination" => variant = Variant::Elimination,
+            "--powerups" => variant = Variant::PowerUps,
+            "--free" => variant = Variant::FreePlacement,
+            "--players" => players = parse_number(args.next(), "--players"),
+            "--size" => match args.next().map(|size| size.parse()) {
+                Some(Ok(parsed)) => size = parsed,
+                Some(Err(err)) => fail(&err.0),
+                None => fail("--size needs COLSxROWS"),
+            },
+            "--layout" => match args.next().map(|path| Layout::load(path)) {
+                Some(Ok(loaded)) => layout = Some(loaded),
+                Some(Err(err)) => fail(&format!("could not load layout: {}", err)),
+                None => fail("--layout needs a file"),
+            },
+            "--order" => match args
+                .next()
+                .map(|order| order.split(',').map(str::parse).collect())
+            {
+                Some(Ok(parsed)) => order = Some(parsed),
+                Some(Err(ProtocolError(err))) => fail(&err),
+                None => fail("--order needs the players in turn, like 3,1,2"),
+            },
+            "--seed" => config.seed = parse_number(args.next(), "--seed"),
+            "--first" => match args.next().map(|first| first.parse()) {
+                // Alternating only means something over a series of games
+                Some(Ok(FirstPlayer::Alternate)) | Some(Err(_)) | None => {
+                    fail("--first needs 1, 2, random or keep")
+                }
+                Some(Ok(first_player)) => config.first_player = first_player,
+            },
+            _ => fail(&format!("unknown option: {}", arg)),
+        }
+    }
+
+    if !(2..=4).contains(&players) {
+        fail("--players needs 2, 3 or 4");
+    }
+    if players > 2 && variant.is_two_player() {
+        fail("pop variants are for two players");
+    }
+
+    let opponent = match ai {
+        Some(ai) => PlayerType::AI(AI { ponder, ..ai }),
+        None => PlayerType::Human,
+    };
+    let mut kinds = vec![opponent; players];
+    kinds[0] = PlayerType::Human;
+    let mut board = Board::new_multiplayer(&kinds)
+        .with_variant(variant)
+        .with_size(size);
+    if let Some(order) = order {
+        board = board
+            .with_turn_order(&order)
+            .unwrap_or_else(|err| fail(&err.0));
+    }
+    if let Some(layout) = layout {
+        board = board
+            .with_layout(&layout)
+            .unwrap_or_else(|err| fail(&format!("could not use layout: {}", err)));
+    }
+    let record = board.play_game_with_config(config, &Broadcaster::new());
+    println!("{}", record);
+}
+
+fn parse_player(spec: &str) -> PlayerType {
+    if let Some(path) = spec.strip_prefix("engine:") {
+        return match ExternalEngine::spawn(path, &[] as &[&str]) {
+            Ok(engine) => PlayerType::Agent(SharedAgent::new(engine)),
+            Err(err) => fail(&format!("could not start {}: {}", path, err)),
+        };
+    }
+    match spec.strip_prefix("level:") {
+        Some(level) => PlayerType::AI(parse_level(level).ai()),
+        None if spec == "human" => PlayerType::Human,
+        None => fail(&format!("unknown player: {}", spec)),
+    }
+}
+
+fn match_series(args: &[String]) {
+    let mut length = MatchLength::BestOf(3);
+    let mut seed = None;
+    let mut specs = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--best-of" => length = MatchLength::BestOf(parse_number(args.next(), "--best-of")),
+            "--first-to" => {
+                let wins = parse_number(args.next(), "--first-to");
+                // Stop a run of draws from going on forever
+                length = MatchLength::FirstTo {
+                    wins,
+                    max_games: wins * 4,
+                };
+            }
+            "--seed" => seed = Some(parse_number(args.next(), "--seed")),
+            _ => specs.push(arg.clone()),
+        }
+    }
+    let [one, two] = specs.as_slice() else {
+        fail("a match needs two players");
+    };
+
+    let mut series = Match::new(parse_player(one), parse_player(two), length);
+    if let Some(seed) = seed {
+        series = series.seed(seed);
+    }
+    let score = series.play(&Broadcaster::new(), |score| {
+        if let Some(record) = score.records.last() {
+            println!("Game {}: {}\n{}", score.games(), record, score);
+        }
+    });
+    match score.leader() {
+        Some(id) => println!("Player {} wins the match, {}", id, score),
+        None => println!("The match is tied, {}", score),
+    }
+}
+
+fn tournament(args: &[String]) {
+    let mut format = Format::RoundRobin;
+    let mut games = 2;
+    let mut seed = None;
+    let mut threads = None;
+    let mut movetime = Duration::from_millis(1000);
+    let mut specs = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--gauntlet" => format = Format::Gauntlet,
+            "--seed" => seed = Some(parse_number(args.next(), "--seed")),
+            "--games" => games = parse_number(args.next(), "--games"),
+            "--threads" => threads = Some(parse_number(args.next(), "--threads")),
+            "--movetime" => {
+                movetime = Duration::from_millis(parse_number(args.next(), "--movetime"))
+            }
+            _ => specs.push(arg.clone()),
+        }
+    }
+    if specs.len() < 2 {
+        fail("a tournament needs at least two entrants");
+    }
+
+    let mut tournament = Tournament::new(format).games_per_pair(games);
+    if let Some(seed) = seed {
+        tournament = tournament.seed(seed);
+    }
+    if let Some(threads) = threads {
+        tournament = tournament.threads(threads);
+    }
+    for spec in &specs {
+        tournament = tournament.entrant(parse_entrant(spec, movetime));
+    }
+
+    println!("{}", tournament.run());
+}
+
+fn sprt(args: &[String]) {
+    let mut config = SprtConfig::default();
+    let mut plies = 2;
+    let mut random_plies = None;
+    let mut seed = None;
+    let mut threads = None;
+    let mut movetime = Duration::from_millis(1000);
+    let mut specs = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--elo0" => config.elo0 = parse_number(args.next(), "--elo0"),
+            "--elo1" => config.elo1 = parse_number(args.next(), "--elo1"),
+            "--alpha" => config.alpha = parse_number(args.next(), "--alpha"),
+            "--beta" => config.beta = parse_number(args.next(), "--beta"),
+            "--max-games" => config.max_games = parse_number(args.next(), "--max-games"),
+            "--openings" => plies = parse_number(args.next(), "--openings"),
+            "--random-plies" => random_plies = Some(parse_number(args.next(), "--random-plies")),
+            "--seed" => seed = Some(parse_number(args.next(), "--seed")),
+            "--threads" => threads = Some(parse_number(args.next(), "--threads")),
+            "--movetime" => {
+                movetime = Duration::from_millis(parse_number(args.next(), "--movetime"))
+            }
+            _ => specs.push(arg.clone()),
+        }
+    }
+    let [candidate, baseline] = specs.as_slice() else {
+        fail("sprt needs a candidate and a baseline");
+    };
+
+    let openings = balanced_openings(
+        plies,
+        AI {
+            depth: 4,
+            ..AI::default()
+        },
+        1000,
+    );
+    println!(
+        "Testing {} against {} from {} openings",
+        candidate,
+        baseline,
+        openings.len()
+    );
+
+    let mut sprt = Sprt::new(config).openings(openings);
+    if let Some(random_plies) = random_plies {
+        sprt = sprt.random_plies(random_plies);
+    }
+    if let Some(seed) = seed {
+        sprt = sprt.seed(seed);
+    }
+    if let Some(threads) = threads {
+        sprt = sprt.threads(threads);
+    }
+    let status = sprt.run(
+        &parse_entrant(candidate, movetime),
+        &parse_entrant(baseline, movetime),
+        |status| println!("{}", status),
+    );
+
+    println!("\n{}", status);
+}
+
+fn book(args: &[String]) {
+    let mut plies = 4;
+    let mut depth = 8;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--plies" => plies = parse_number(args.next(), "--plies"),
+            "--depth" => depth = parse_number(args.next(), "--depth"),
+            _ => output = Some(arg.clone()),
+        }
+    }
+    let Some(output) = output else {
+        fail("book needs a file to write to");
+    };
+
+    let ai = AI {
+        depth,
+        ..AI::default()
+    };
+    let book = OpeningBook::generate(plies, ai, |done, total| {
+        eprint!("\rSearched {}/{} positions", done, total);
+    });
+    eprintln!();
+
+    if let Err(err) = book.save(&output) {
+        eprintln!("Could not write {}: {}", output, err);
+        process::exit(1);
+    }
+    println!("Wrote {} positions to {}", book.len(), output);
+}
+
+fn tune(args: &[String]) {
+    let mut games = 200;
+    let mut depth = 4;
+    let mut random_plies = 6;
+    let mut passes = 100;
+    let mut seed = rand::random();
+    let mut start = EvalWeights::default();
+    let mut positions_file = None;
+    let mut output = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--games" => games = parse_number(args.next(), "--games"),
+            "--depth" => depth = parse_number(args.next(), "--depth"),
+            "--random-plies" => random_plies = parse_number(args.next(), "--random-plies"),
+            "--passes" => passes = parse_number(args.next(), "--passes"),
+            "--seed" => seed = parse_number(args.next(), "--seed"),
+            "--start" => {
+                let Some(path) = args.next() else {
+                    fail("--start needs a weights file");
+                };
+                start = EvalWeights::load(path).unwrap_or_else(|err| {
+                    fail(&format!("could not load weights from {}: {}", path, err))
+                });
+            }
+            "--positions" => positions_file = args.next().cloned(),
+            _ => output = Some(arg.clone()),
+        }
+    }
+    let Some(output) = output else {
+        fail("tune needs a file to write the weights to");
+    };
+
+    // Reuse positions from an earlier run if the file exists, otherwise generate and keep them
+    let existing = positions_file
+        .as_deref()
+        .filter(|path| Path::new(path).exists());
+    let positions = match existing.map(|path| (path, load_positions(path))) {
+        Some((_, Ok(positions))) => positions,
+        Some((path, Err(err))) => fail(&format!("could not load positions from {}: {}", path, err)),
+        None => {
+            let ai = AI {
+                depth,
+                weights: start,
+                ..AI::default()
+            };
+            let mut rng = StdRng::seed_from_u64(seed);
+            let positions = self_play_positions(games, ai, random_plies, &mut rng);
+            println!("To generate the same positions again use --seed {}", seed);
+            if let Some(path) = &positions_file {
+                if let Err(err) = save_positions(&positions, path) {
+                    eprintln!("Could not write {}: {}", path, err);
+                }
+            }
+            positions
+        }
+    };
+    println!("Tuning on {} positions", positions.len());
+
+    let mut tuner = Tuner::new(positions);
+    tuner.fit_scale(&start);
+    println!(
+        "Scale {:.0}, starting error {:.6}",
+        tuner.scale,
+        tuner.error(&start)
+    );
+
+    let weights = tuner.tune(start, passes, |pass, error| {
+        println!("Pass {}: error {:.6}", pass, error);
+    });
+
+    if let Err(err) = weights.save(&output) {
+        eprintln!("Could not write {}: {}", output, err);
+        process::exit(1);
+    }
+    println!("Wrote tuned weights to {}\n\n{}", output, weights);
+}
+
+/// Positions searched by `bench`, as the columns played to reach them.
+const BENCH_POSITIONS: [&[usize]; 4] = [&[], &[3, 3], &[3, 2, 3, 3, 2], &[3, 3, 3, 3, 2, 4, 4, 2]];
+
+/// Times the same searches on one thread and on `--threads` to show the speedup.
+fn bench(args: &[String]) {
+    let mut depth = 10;
+    let mut threads = thread::available_parallelism().map_or(4, |threads| threads.get());
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--depth" => depth = parse_number(args.next(), "--depth"),
+            "--threads" => threads = parse_number(args.next(), "--threads"),
+            _ => fail(&format!("unknown option: {}", arg)),
+        }
+    }
+
+    let single = AI {
+        depth,
+        ..AI::default()
+    };
+    let parallel = AI {
+        threads,
+        ..single.clone()
+    };
+
+    println!("Depth {}, 1 thread against {}", depth, threads);
+    let mut totals = [Duration::ZERO; 2];
+    for moves in BENCH_POSITIONS {
+        let board = board_from_moves(moves).expect("bench positions are legal");
+        let mut times = [Duration::ZERO; 2];
+        for (ai, time) in [&single, &parallel].into_iter().zip(&mut times) {
+            let started = Instant::now();
+            ai.analyse(&board);
+            *time = started.elapsed();
+        }
+        totals[0] += times[0];
+        totals[1] += times[1];
+
+        let name = if moves.is_empty() {
+            "-".to_string()
+        } else {
+            moves.iter().map(|col| col.to_string()).collect()
+        };
+        println!(
+            "{:<10} {:>9.2?} {:>9.2?} {:>6.2}x",
+            name,
+            times[0],
+            times[1],
+            times[0].as_secs_f64() / times[1].as_secs_f64()
+        );
+    }
+    println!(
+        "{:<10} {:>9.2?} {:>9.2?} {:>6.2}x",
+        "total",
+        totals[0],
+        totals[1],
+        totals[0].as_secs_f64() / totals[1].as_secs_f64()
+    );
+}
+
 fn main() {
-    println!("Hello, world!");
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args.first().map(String::as_str) {
+        None => Board::new_human_vs_human().play_game(),
+        Some("play") => play(&args[1..]),
+        Some("match") => match_series(&args[1..]),
+        Some("tournament") => tournament(&args[1..]),
+        Some("sprt") => sprt(&args[1..]),
+        Some("book") => book(&args[1..]),
+        Some("tune") => tune(&args[1..]),
+        Some("bench") => bench(&args[1..]),
+        Some(command) => fail(&format!("unknown command: {}", command)),
+    }
 }
@@ -0,0 +1,314 @@
+use crate::ai::AI;
+use crate::game::{Board, GameState, Play};
+use crate::player::PlayerId;
+use crate::protocol::board_from_moves;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Why an agent could not produce a move. Any of these forfeits the game.
+#[derive(Clone, PartialEq, Debug)]
+pub enum AgentError {
+    Timeout,
+    Crashed(String),
+    NoMove,
+    IllegalMove(Play),
+    /// The agent cannot play this kind of game, e.g. an engine on a Pop Out board.
+    Unsupported(String),
+}
+
+impl fmt::Display for AgentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AgentError::Timeout => write!(f, "did not move in time"),
+            AgentError::Crashed(reason) => write!(f, "crashed: {}", reason),
+            AgentError::NoMove => write!(f, "could not find a move"),
+            AgentError::IllegalMove(play) => write!(f, "played the illegal move {}", play),
+            AgentError::Unsupported(what) => write!(f, "cannot play {}", what),
+        }
+    }
+}
+
+impl std::error::Error for AgentError {}
+
+/**
+ * Anything that can choose moves without a human at the keyboard, e.g. our
+ * own `AI` or an `ExternalEngine` running in another process.
+ */
+pub trait Agent {
+    fn name(&self) -> String;
+
+    /// Picks a move for the player to move. `plays` are the moves made so far.
+    fn choose_move(&mut self, board: &Board, plays: &[Play]) -> Result<Play, AgentError>;
+}
+
+impl Agent for AI {
+    fn name(&self) -> String {
+        format!("AI (depth {})", self.depth)
+    }
+
+    fn choose_move(&mut self, board: &Board, _plays: &[Play]) -> Result<Play, AgentError> {
+        self.best_play(board).ok_or(AgentError::NoMove)
+    }
+}
+
+/**
+ * An agent that plays on a `Board` as `PlayerType::Agent`. Boards are cloned
+ * freely, so the clones share one agent rather than each getting their own.
+ */
+#[derive(Clone)]
+pub struct SharedAgent {
+    name: String,
+    agent: Arc<Mutex<dyn Agent + Send>>,
+}
+
+impl SharedAgent {
+    pub fn new(agent: impl Agent + Send + 'static) -> Self {
+        SharedAgent {
+            name: agent.name(),
+            agent: Arc::new(Mutex::new(agent)),
+        }
+    }
+}
+
+impl Agent for SharedAgent {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn choose_move(&mut self, board: &Board, plays: &[Play]) -> Result<Play, AgentError> {
+        let mut agent = self.agent.lock().expect("agent lock poisoned");
+        agent.choose_move(board, plays)
+    }
+}
+
+/// The same agent, not just one with the same name.
+impl PartialEq for SharedAgent {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.agent, &other.agent)
+    }
+}
+
+impl fmt::Debug for SharedAgent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SharedAgent").field(&self.name).finish()
+    }
+}
+
+/**
+ * An `AI` that draws its random choices from its own seeded generator
+ * instead of the thread's, so games against it can be replayed.
+ */
+pub struct SeededAI {
+    pub ai: AI,
+    rng: StdRng,
+}
+
+impl SeededAI {
+    pub fn new(ai: AI, seed: u64) -> Self {
+        SeededAI {
+            ai,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Agent for SeededAI {
+    fn name(&self) -> String {
+        self.ai.name()
+    }
+
+    fn choose_move(&mut self, board: &Board, _plays: &[Play]) -> Result<Play, AgentError> {
+        self.ai
+            .choose_play(board, &mut self.rng)
+            .ok_or(AgentError::NoMove)
+    }
+}
+
+/// How a game between two agents ended.
+#[derive(Clone, PartialEq, Debug)]
+pub struct GameOutcome {
+    pub state: GameState,
+    pub moves: Vec<Play>,
+    /// See `Board::scores`.
+    pub scores: Option<Vec<usize>>,
+    /// Set when the game was lost by forfeit rather than played out.
+    pub forfeit: Option<(PlayerId, AgentError)>,
+}
+
+impl GameOutcome {
+    pub fn winner(&self) -> Option<PlayerId> {
+        match self.state {
+            GameState::Win(id) => Some(id),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * Plays a game between two agents from the given board. `agents[0]` plays as
+ * Player 1 and `agents[1]` as Player 2. An agent that fails to move or plays
+ * an illegal move loses the game.
+ */
+pub fn play_agents(board: &mut Board, agents: [&mut dyn Agent; 2]) -> GameOutcome {
+    play_agents_from(board, Vec::new(), agents)
+}
+
+/**
+ * Plays the game on from a set opening. Whoever is to move after `opening`
+ * moves first; `agents` are still given as Player 1 and Player 2.
+ */
+pub fn play_opening(opening: &[usize], agents: [&mut dyn Agent; 2]) -> GameOutcome {
+    let mut board = board_from_moves(opening).expect("opening must be legal");
+    let moves = opening.iter().map(|&col| Play::drop(col)).collect();
+    play_agents_from(&mut board, moves, agents)
+}
+
+fn play_agents_from(
+    board: &mut Board,
+    mut moves: Vec<Play>,
+    agents: [&mut dyn Agent; 2],
+) -> GameOutcome {
+    let [one, two] = agents;
+
+    while *board.get_game_state() == GameState::InProgress {
+        let id = board.get_current_player().id;
+        let agent = match id {
+            PlayerId::One => &mut *one,
+            PlayerId::Two => &mut *two,
+            id => unreachable!("Player {} in a game between two agents", id),
+        };
+
+        let result = agent
+            .choose_move(board, &moves)
+            .and_then(|play| match board.play(play) {
+                Some(_) => Ok(play),
+                None => Err(AgentError::IllegalMove(play)),
+            });
+
+        match result {
+            Ok(play) => moves.push(play),
+            Err(err) => {
+                board.forfeit();
+                return GameOutcome {
+                    state: *board.get_game_state(),
+                    moves,
+                    scores: board.scores(),
+                    forfeit: Some((id, err)),
+                };
+            }
+        }
+    }
+
+    GameOutcome {
+        state: *board.get_game_state(),
+        moves,
+        scores: board.scores(),
+        forfeit: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::difficulty::Difficulty;
+    use crate::game::{FirstPlayer, GameConfig};
+    use crate::player::PlayerType;
+    use crate::spectator::Broadcaster;
+
+    /// Always plays the same column, however full it is.
+    struct Stubborn(usize);
+
+    impl Agent for Stubborn {
+        fn name(&self) -> String {
+            "Stubborn".to_string()
+        }
+
+        fn choose_move(&mut self, _board: &Board, _plays: &[Play]) -> Result<Play, AgentError> {
+            Ok(Play::drop(self.0))
+        }
+    }
+
+    #[test]
+    fn test_ai_vs_ai_plays_to_the_end() {
+        let mut board = Board::new_human_vs_human();
+        let mut one = AI {
+            depth: 2,
+            ..AI::default()
+        };
+        let mut two = AI {
+            depth: 2,
+            ..AI::default()
+        };
+
+        let outcome = play_agents(&mut board, [&mut one, &mut two]);
+
+        assert_ne!(outcome.state, GameState::InProgress);
+        assert_eq!(outcome.state, *board.get_game_state());
+        assert!(outcome.forfeit.is_none());
+    }
+
+    #[test]
+    fn test_seeded_ai_replays() {
+        let play = |seed| {
+            let ai = Difficulty::Beginner.ai();
+            let mut one = SeededAI::new(ai.clone(), seed);
+            let mut two = SeededAI::new(ai, seed + 1);
+            play_agents(&mut Board::new_human_vs_human(), [&mut one, &mut two]).moves
+        };
+
+        assert_eq!(play(5), play(5));
+        assert!((0..3).any(|seed| play(seed) != play(5)));
+    }
+
+    #[test]
+    fn test_play_opening() {
+        let mut one = Stubborn(0);
+        let mut two = Stubborn(1);
+
+        // After 3 3 Player 1 is to move, and wins vertically in column 0
+        let outcome = play_opening(&[3, 3], [&mut one, &mut two]);
+
+        let cols: Vec<usize> = outcome.moves.iter().map(|play| play.col).collect();
+        assert_eq!(cols, [3, 3, 0, 1, 0, 1, 0, 1, 0]);
+        assert_eq!(outcome.winner(), Some(PlayerId::One));
+    }
+
+    #[test]
+    fn test_illegal_move_forfeits() {
+        let mut board = Board::new_human_vs_human();
+        let mut one = Stubborn(0);
+        let mut two = Stubborn(0);
+
+        let outcome = play_agents(&mut board, [&mut one, &mut two]);
+
+        // Column 0 holds six discs, so Player 1 is the first to overflow it
+        assert_eq!(outcome.moves.len(), 6);
+        assert_eq!(
+            outcome.forfeit,
+            Some((PlayerId::One, AgentError::IllegalMove(Play::drop(0))))
+        );
+        assert_eq!(outcome.winner(), Some(PlayerId::Two));
+        assert_eq!(board.get_game_state(), &outcome.state);
+    }
+
+    #[test]
+    fn test_agents_play_on_the_board() {
+        let stubborn = |col| PlayerType::Agent(SharedAgent::new(Stubborn(col)));
+        let config = GameConfig {
+            first_player: FirstPlayer::PlayerOne,
+            ..GameConfig::default()
+        };
+
+        // Player 1 wins down column 0
+        let mut board = Board::new(stubborn(0), stubborn(1));
+        let record = board.play_game_with_config(config, &Broadcaster::new());
+        assert_eq!(record.state, GameState::Win(PlayerId::One));
+
+        // Both play column 0, so Player 1 is the first to overflow it
+        let mut board = Board::new(stubborn(0), stubborn(0));
+        board.play_game_with_config(config, &Broadcaster::new());
+        assert_eq!(board.get_game_state(), &GameState::Win(PlayerId::Two));
+    }
+}
@@ -1,13 +1,142 @@
-use crate::game::{Board, GameState, COLS};
-use crate::player::Player;
+use crate::book::BookSettings;
+use crate::eval::{EvalMode, EvalWeights};
+use crate::game::{Board, BoardSize, GameState, MoveKind, Play};
+use crate::player::PlayerId;
+use crate::rules::Ruleset;
+use rand::Rng;
+use std::cmp::Reverse;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 
 pub struct AI {
     pub depth: usize,
+    /// Consulted before searching, while the game is young enough.
+    pub book: Option<BookSettings>,
+    pub weights: EvalWeights,
+    /// Threads to split the moves at the root between. 1 searches on the calling thread.
+    pub threads: usize,
+    /**
+     * Search every root move with the full window when using threads, so
+     * the result does not depend on which thread finishes first. Slower,
+     * but the same score and move as a single thread every time.
+     */
+    pub deterministic: bool,
+    /// Search during the opponent's turn in `Board::play_game`, see `Ponder`.
+    pub ponder: bool,
+    /**
+     * Above 0, moves are picked at random with probabilities by softmax over
+     * their scores: a move `temperature` worse than the best is e (2.7) times
+     * less likely. See `Difficulty` for sensible values.
+     */
+    pub temperature: f64,
+    /// Chance of playing a random legal move without searching at all.
+    pub blunder_chance: f64,
+    /// How to search games with more than two players.
+    pub multi_search: MultiSearch,
+}
+
+/**
+ * With three or four players a move that is good for one opponent can be bad
+ * for another, so there is no single score to negate between turns.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MultiSearch {
+    /**
+     * Every player scores the position for themselves and picks the move
+     * best for them. True to how people play, but nothing can be pruned.
+     */
+    MaxN,
+    /**
+     * Assume all the opponents have ganged up on us, which makes it a two
+     * sided game again that alpha-beta can prune. Plays safe and searches
+     * deeper in the same time.
+     */
+    #[default]
+    Paranoid,
+}
+
+impl Default for AI {
+    fn default() -> Self {
+        AI::new(2)
+    }
+}
+
+/// Limits for `AI::search`. Anything left as `None` falls back to the AI's own depth.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct SearchLimits {
+    pub depth: Option<usize>,
+    pub movetime: Option<Duration>,
+}
+
+/**
+ * Score of a win on the very next move. A win `n` plies from the root scores
+ * `WIN_SCORE - n` and a loss `n` plies away `-(WIN_SCORE - n)`, so faster wins
+ * and slower losses are preferred. Evaluations of unfinished positions are
+ * kept below every such score.
+ */
+pub const WIN_SCORE: i32 = 10_000_000;
+
+/**
+ * The furthest from the root a search can find a win: every cell of the
+ * biggest board. Searches are cut to this depth, however deep they are
+ * asked to go, so a proven score is never mistaken for an evaluation.
+ */
+const MAX_PLIES: i32 = (BoardSize::MAX_SIDE * BoardSize::MAX_SIDE) as i32;
+
+/// `depth`, cut to `MAX_PLIES`.
+fn depth_limit(depth: usize) -> usize {
+    depth.min(MAX_PLIES as usize)
+}
+
+/// Above any score the search can return.
+const INFINITY: i32 = WIN_SCORE + 1;
+
+/// Whether `score` is a forced win or loss rather than an evaluation.
+pub fn is_proven(score: i32) -> bool {
+    score.abs() >= WIN_SCORE - MAX_PLIES
+}
+
+/// Number of plies until the game is won or lost, for proven scores.
+pub fn plies_to_end(score: i32) -> Option<usize> {
+    is_proven(score).then(|| (WIN_SCORE - score.abs()) as usize)
+}
+
+/// What a search found for the position it was given.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SearchResult {
+    /// From the point of view of the player to move.
+    pub score: i32,
+    pub best_move: Option<Play>,
+    /// The score is a forced win or loss, searching deeper would not change it.
+    pub proven: bool,
+}
+
+/// The result of one completed iteration of `AI::search`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SearchInfo {
+    pub depth: usize,
+    pub score: i32,
+    pub best_move: Option<Play>,
+    pub pv: Vec<Play>,
+    pub nodes: u64,
+}
+
+impl SearchInfo {
+    pub fn result(&self) -> SearchResult {
+        SearchResult {
+            score: self.score,
+            best_move: self.best_move,
+            proven: is_proven(self.score),
+        }
+    }
 }
 
 impl AI {
+    /// Plain depth levels, see `Difficulty` for levels that also play like people.
     pub fn new(diff: usize) -> Self {
         let depth = match diff {
             1 => 4,
@@ -16,89 +145,723 @@ impl AI {
             10 => 10, // Test a strong AI
             _ => 6,   // default depth for any other value
         };
-        AI { depth: depth }
+        AI {
+            depth,
+            book: None,
+            weights: EvalWeights::default(),
+            threads: 1,
+            deterministic: false,
+            ponder: false,
+            temperature: 0.0,
+            blunder_chance: 0.0,
+            multi_search: MultiSearch::default(),
+        }
+    }
+
+    /// Whether `best_move` would look in the book rather than search.
+    pub fn in_book(&self, board: &Board) -> bool {
+        // Book keys have no turn in them, so they only hold for games Player 1 started
+        let book_turn = match board.moves_played() % 2 {
+            0 => PlayerId::One,
+            _ => PlayerId::Two,
+        };
+        board.is_classic()
+            && board.get_current_player().id == book_turn
+            && self
+                .book
+                .as_ref()
+                .is_some_and(|settings| board.moves_played() < settings.max_plies)
+    }
+
+    /**
+     * The column to drop in, for games of drops only. Panics if the best
+     * move is anything else, use `best_play` where it can be.
+     */
+    pub fn best_move(&self, board: &Board) -> Option<usize> {
+        let play = self.best_play(board)?;
+        assert_eq!(play.kind, MoveKind::Drop, "best_move cannot play {}", play);
+        Some(play.col)
+    }
+
+    pub fn best_play(&self, board: &Board) -> Option<Play> {
+        self.choose_play(board, &mut rand::thread_rng())
+    }
+
+    /// `best_play`, taking any random choices with `rng`.
+    pub fn choose_play(&self, board: &Board, rng: &mut impl Rng) -> Option<Play> {
+        if let Some(settings) = &self.book {
+            if self.in_book(board) {
+                // A book can be edited by hand, so anything illegal in it is left to the search
+                let book_play = settings
+                    .book
+                    .choose_move(board, settings.variety, rng)
+                    .map(|book_move| Play::drop(book_move.col))
+                    .filter(|&play| board.valid_play(play));
+                if book_play.is_some() {
+                    return book_play;
+                }
+            }
+        }
+
+        let legal = board.legal_plays();
+        if legal.is_empty() {
+            return None;
+        }
+        if self.blunder_chance > 0.0 && rng.gen_bool(self.blunder_chance.min(1.0)) {
+            return Some(legal[rng.gen_range(0..legal.len())]);
+        }
+        if self.temperature <= 0.0 {
+            return self.analyse(board).best_move;
+        }
+
+        let stop = AtomicBool::new(false);
+        let rules = board.rules();
+        let scores =
+            Searcher::new(self, rules, &stop, None).root_scores(board, depth_limit(self.depth));
+        let best = scores.iter().map(|&(_, score)| score).max()?;
+        let weights: Vec<f64> = scores
+            .iter()
+            .map(|&(_, score)| ((score - best) as f64 / self.temperature).exp())
+            .collect();
+
+        let mut pick = rng.gen::<f64>() * weights.iter().sum::<f64>();
+        for (&(play, _), weight) in scores.iter().zip(weights) {
+            if pick < weight {
+                return Some(play);
+            }
+            pick -= weight;
+        }
+        // Only reached through rounding
+        scores.last().map(|&(play, _)| play)
+    }
+
+    /// Searches `board` to the AI's depth, without the book.
+    pub fn analyse(&self, board: &Board) -> SearchResult {
+        self.analyse_with(board, board.rules())
+    }
+
+    /// `analyse`, playing by `rules` rather than the board's own.
+    pub fn analyse_with<R: Ruleset + ?Sized>(&self, board: &Board, rules: &R) -> SearchResult {
+        let stop = AtomicBool::new(false);
+        let mut searcher = Searcher::new(self, rules, &stop, None);
+        searcher.root(board, depth_limit(self.depth)).result()
+    }
+
+    /**
+     * Iterative deepening search. `on_info` is called after every completed depth,
+     * and the search can be cut short by the time limit or by setting `stop`
+     * from another thread. The deepest completed iteration is returned, or the
+     * first one that proved a win or loss.
+     */
+    pub fn search(
+        &self,
+        board: &Board,
+        limits: SearchLimits,
+        stop: &AtomicBool,
+        on_info: impl FnMut(&SearchInfo),
+    ) -> SearchInfo {
+        self.search_with(board, board.rules(), limits, stop, on_info)
+    }
+
+    /// `search`, playing by `rules` rather than the board's own.
+    pub fn search_with<R: Ruleset + ?Sized>(
+        &self,
+        board: &Board,
+        rules: &R,
+        limits: SearchLimits,
+        stop: &AtomicBool,
+        mut on_info: impl FnMut(&SearchInfo),
+    ) -> SearchInfo {
+        let max_depth = depth_limit(limits.depth.unwrap_or(self.depth)).max(1);
+        let deadline = limits.movetime.map(|movetime| Instant::now() + movetime);
+        let mut searcher = Searcher::new(self, rules, stop, deadline);
+
+        // Until depth 1 completes, any legal move is better than nothing
+        let mut result = SearchInfo {
+            depth: 0,
+            score: 0,
+            best_move: rules.legal_plays(board).first().copied(),
+            pv: Vec::new(),
+            nodes: 0,
+        };
+
+        for depth in 1..=max_depth {
+            let info = searcher.root(board, depth);
+            if searcher.aborted {
+                break;
+            }
+            on_info(&info);
+            result = info;
+            if is_proven(result.score) {
+                break;
+            }
+        }
+
+        result.nodes = searcher.nodes;
+        result
+    }
+}
+
+/// State shared by one search: the rules, node count and the ways it can be stopped.
+struct Searcher<'a, R: Ruleset + ?Sized> {
+    rules: &'a R,
+    weights: &'a EvalWeights,
+    threads: usize,
+    deterministic: bool,
+    multi_search: MultiSearch,
+    nodes: u64,
+    stop: &'a AtomicBool,
+    deadline: Option<Instant>,
+    aborted: bool,
+    /// Who the search is for, set at the root.
+    root: Option<PlayerId>,
+}
+
+// Derived Clone would need R: Clone, but only the reference is copied
+impl<R: Ruleset + ?Sized> Clone for Searcher<'_, R> {
+    fn clone(&self) -> Self {
+        Searcher { ..*self }
     }
-    pub fn best_move(self, board: &Board) -> Option<usize> {
-        let mut alpha: i32 = i32::MIN + 1;
-        let beta: i32 = i32::MAX;
-        let mut best_col: Option<usize> = None;
-        let mut best_score: i32 = i32::MIN + 1;
+}
 
-        let ai_player = board.get_current_player(); // Save this for evaluation perspective
+impl<'a, R: Ruleset + ?Sized> Searcher<'a, R> {
+    fn new(ai: &'a AI, rules: &'a R, stop: &'a AtomicBool, deadline: Option<Instant>) -> Self {
+        Searcher {
+            rules,
+            weights: &ai.weights,
+            threads: ai.threads.max(1),
+            deterministic: ai.deterministic,
+            multi_search: ai.multi_search,
+            nodes: 0,
+            stop,
+            deadline,
+            aborted: false,
+            root: None,
+        }
+    }
 
-        for col in 0..COLS {
+    fn should_stop(&mut self) -> bool {
+        // Checking the clock on every node is wasteful
+        if !self.aborted && self.nodes % 1024 == 1 {
+            let out_of_time = self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline);
+            self.aborted = out_of_time || self.stop.load(Ordering::Relaxed);
+        }
+        self.aborted
+    }
+
+    fn root(&mut self, board: &Board, depth: usize) -> SearchInfo {
+        self.root = Some(board.get_current_player().id);
+        if board.get_players().len() > 2 {
+            return self.root_multi(board, depth);
+        }
+        if self.threads > 1 {
+            return self.root_parallel(board, depth);
+        }
+
+        let mut alpha = -INFINITY;
+        let beta = INFINITY;
+        let mut best_play: Option<Play> = None;
+        let mut best_score = -INFINITY;
+        let mut pv = Vec::new();
+        let mut child_pv = Vec::new();
+
+        for play in self.rules.legal_plays(board) {
             // Simulate move
             let mut temp_board = board.clone();
-            if temp_board.play_move(col).is_some() {
-                let raw_score =
-                    self.negamax(&temp_board, self.depth - 1, -beta, -alpha, &ai_player);
-
-                let score = if raw_score == i32::MIN {
-                    i32::MIN + 1
-                } else {
-                    -raw_score
-                };
+            if self.rules.apply(&mut temp_board, play).is_some() {
+                let score =
+                    self.child(board, &temp_board, depth - 1, 1, alpha, beta, &mut child_pv);
+                if self.aborted {
+                    break;
+                }
 
                 if score > best_score {
                     best_score = score;
-                    best_col = Some(col);
+                    best_play = Some(play);
+                    pv.clear();
+                    pv.push(play);
+                    pv.extend_from_slice(&child_pv);
                 }
 
                 alpha = alpha.max(score);
             }
         }
 
-        best_col
+        SearchInfo {
+            depth,
+            // No legal moves, the game is already over
+            score: if best_play.is_some() { best_score } else { 0 },
+            best_move: best_play,
+            pv,
+            nodes: self.nodes,
+        }
+    }
+
+    /**
+     * Root splitting: after the first move, the moves at the root are handed
+     * out to the threads one at a time. Each is searched against the best
+     * score any thread has found so far, or with the full window when
+     * deterministic.
+     */
+    fn root_parallel(&mut self, board: &Board, depth: usize) -> SearchInfo {
+        let plays = self.rules.legal_plays(board);
+        if plays.is_empty() {
+            return SearchInfo {
+                depth,
+                score: 0,
+                best_move: None,
+                pv: Vec::new(),
+                nodes: self.nodes,
+            };
+        }
+
+        let best_score = AtomicI32::new(-INFINITY);
+        let nodes = AtomicU64::new(0);
+        let aborted = AtomicBool::new(false);
+        // Play, score, whether the score is exact rather than a bound, and the line after it
+        let results = Mutex::new(Vec::new());
+
+        let search_play = |worker: &mut Searcher<'a, R>, play: Play| {
+            let mut temp_board = board.clone();
+            worker.rules.apply(&mut temp_board, play);
+            let alpha = if worker.deterministic {
+                -INFINITY
+            } else {
+                best_score.load(Ordering::Relaxed)
+            };
+
+            let mut child_pv = Vec::new();
+            let score = worker.child(
+                board,
+                &temp_board,
+                depth - 1,
+                1,
+                alpha,
+                INFINITY,
+                &mut child_pv,
+            );
+            if worker.aborted {
+                aborted.store(true, Ordering::Relaxed);
+                return;
+            }
+
+            best_score.fetch_max(score, Ordering::Relaxed);
+            let mut results = results.lock().unwrap();
+            results.push((play, score, score > alpha, child_pv));
+        };
+
+        let worker = || Searcher {
+            threads: 1,
+            nodes: 0,
+            ..self.clone()
+        };
+
+        // The first move is searched alone so the others have a bound to prune against
+        let mut first = worker();
+        search_play(&mut first, plays[0]);
+        nodes.fetch_add(first.nodes, Ordering::Relaxed);
+
+        let next = AtomicUsize::new(1);
+        thread::scope(|scope| {
+            for _ in 0..self.threads.min(plays.len() - 1) {
+                scope.spawn(|| {
+                    let mut worker = worker();
+                    while !aborted.load(Ordering::Relaxed) {
+                        let Some(&play) = plays.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                            break;
+                        };
+                        search_play(&mut worker, play);
+                    }
+                    nodes.fetch_add(worker.nodes, Ordering::Relaxed);
+                });
+            }
+        });
+
+        self.nodes += nodes.into_inner();
+        self.aborted |= aborted.into_inner();
+
+        // A bound can equal the best score but never beat it; ties go to the earliest play
+        let order = |play: &Play| plays.iter().position(|p| p == play);
+        let best = results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .max_by_key(|(play, score, exact, _)| (*score, *exact, Reverse(order(play))));
+
+        let (best_move, score, pv) = match best {
+            Some((play, score, _, child_pv)) => {
+                let mut pv = vec![play];
+                pv.extend(child_pv);
+                (Some(play), score, pv)
+            }
+            // Stopped before the first move was done
+            None => (None, 0, Vec::new()),
+        };
+
+        SearchInfo {
+            depth,
+            score,
+            best_move,
+            pv,
+            nodes: self.nodes,
+        }
+    }
+
+    /// The exact score of every legal play at the root, in `legal_plays` order.
+    fn root_scores(&mut self, board: &Board, depth: usize) -> Vec<(Play, i32)> {
+        let root = board.get_current_player().id;
+        self.root = Some(root);
+        let multi = board.get_players().len() > 2;
+        let mut pv = Vec::new();
+        self.rules
+            .legal_plays(board)
+            .into_iter()
+            .filter_map(|play| {
+                let mut temp_board = board.clone();
+                self.rules.apply(&mut temp_board, play)?;
+                if multi {
+                    let score = self.multi_child(
+                        &temp_board,
+                        depth - 1,
+                        1,
+                        root,
+                        -INFINITY,
+                        INFINITY,
+                        &mut pv,
+                    );
+                    return Some((play, score));
+                }
+                let score = self.child(
+                    board,
+                    &temp_board,
+                    depth - 1,
+                    1,
+                    -INFINITY,
+                    INFINITY,
+                    &mut pv,
+                );
+                Some((play, score))
+            })
+            .collect()
+    }
+
+    /**
+     * Score of `child` for whoever is to move in `board`. Usually the turn
+     * passes and the child's score is negated, but some rules let the same
+     * player move again.
+     */
+    #[allow(clippy::too_many_arguments)]
+    fn child(
+        &mut self,
+        board: &Board,
+        child: &Board,
+        depth: usize,
+        ply: i32,
+        alpha: i32,
+        beta: i32,
+        pv: &mut Vec<Play>,
+    ) -> i32 {
+        if child.get_current_player().id == board.get_current_player().id {
+            self.negamax(child, depth, ply, alpha, beta, pv)
+        } else {
+            -self.negamax(child, depth, ply, -beta, -alpha, pv)
+        }
     }
 
+    /// Score of `board` for the player to move, `ply` moves below the root.
     fn negamax(
-        &self,
+        &mut self,
         board: &Board,
         depth: usize,
+        ply: i32,
         mut alpha: i32,
         beta: i32,
-        ai_player: &Player,
+        pv: &mut Vec<Play>,
     ) -> i32 {
         // board is a copy of the main board. The AI will make moves on it and the state of the game will be changed
+        self.nodes += 1;
+        pv.clear();
 
-        if board.get_game_state() != &GameState::InProgress || depth == 0 {
-            let score = board.clone().evaluate(*ai_player);
+        if self.should_stop() {
+            return 0;
+        }
+
+        match board.get_game_state() {
+            // Usually whoever won made the last move, but popping can hand the opponent a four
+            GameState::Win(winner) if *winner == board.get_current_player().id => {
+                return WIN_SCORE - ply
+            }
+            GameState::Win(_) => return -(WIN_SCORE - ply),
+            GameState::Draw => return 0,
+            GameState::InProgress if depth == 0 => {
+                let limit = WIN_SCORE - MAX_PLIES - 1;
+                let id = board.get_current_player().id;
+                let score = match self.root {
+                    // A one-sided evaluation has to be taken from our side
+                    Some(root) if self.weights.mode == EvalMode::Careless && id != root => {
+                        -self.rules.evaluate(board, root, self.weights)
+                    }
+                    _ => self.rules.evaluate(board, id, self.weights),
+                };
+                return score.clamp(-limit, limit);
+            }
+            GameState::InProgress => {}
+        }
+
+        let mut max_score = -INFINITY;
+        let mut child_pv = Vec::new();
+
+        for play in self.rules.legal_plays(board) {
+            let mut temp_board = board.clone();
+            if self.rules.apply(&mut temp_board, play).is_some() {
+                let score = self.child(
+                    board,
+                    &temp_board,
+                    depth - 1,
+                    ply + 1,
+                    alpha,
+                    beta,
+                    &mut child_pv,
+                );
+                if self.aborted {
+                    return 0;
+                }
+
+                if score > max_score {
+                    max_score = score;
+                    pv.clear();
+                    pv.push(play);
+                    pv.extend_from_slice(&child_pv);
+                }
+                alpha = alpha.max(score);
+
+                if alpha >= beta {
+                    break; // β cut-off
+                }
+            }
+        }
+        max_score
+    }
+
+    /// `root` for three or four players, by `multi_search`.
+    fn root_multi(&mut self, board: &Board, depth: usize) -> SearchInfo {
+        let root = board.get_current_player().id;
+        let mut alpha = -INFINITY;
+        let mut best_play: Option<Play> = None;
+        let mut best_score = -INFINITY;
+        let mut pv = Vec::new();
+        let mut child_pv = Vec::new();
+
+        for play in self.rules.legal_plays(board) {
+            let mut temp_board = board.clone();
+            if self.rules.apply(&mut temp_board, play).is_some() {
+                let score = self.multi_child(
+                    &temp_board,
+                    depth - 1,
+                    1,
+                    root,
+                    alpha,
+                    INFINITY,
+                    &mut child_pv,
+                );
+                if self.aborted {
+                    break;
+                }
+
+                if score > best_score {
+                    best_score = score;
+                    best_play = Some(play);
+                    pv.clear();
+                    pv.push(play);
+                    pv.extend_from_slice(&child_pv);
+                }
+                alpha = alpha.max(score);
+            }
+        }
+
+        SearchInfo {
+            depth,
+            score: if best_play.is_some() { best_score } else { 0 },
+            best_move: best_play,
+            pv,
+            nodes: self.nodes,
+        }
+    }
+
+    /// Score of `board` for `root`, searched by `multi_search`. The window is only used by paranoid search.
+    #[allow(clippy::too_many_arguments)]
+    fn multi_child(
+        &mut self,
+        board: &Board,
+        depth: usize,
+        ply: i32,
+        root: PlayerId,
+        alpha: i32,
+        beta: i32,
+        pv: &mut Vec<Play>,
+    ) -> i32 {
+        match self.multi_search {
+            MultiSearch::Paranoid => self.paranoid(board, depth, ply, root, alpha, beta, pv),
+            MultiSearch::MaxN => self.max_n(board, depth, ply, pv)[root.index()],
+        }
+    }
+
+    /**
+     * The score of a finished position for `id`, or of an unfinished one once
+     * the depth runs out.
+     */
+    fn leaf_score(&self, board: &Board, depth: usize, ply: i32, id: PlayerId) -> Option<i32> {
+        match board.get_game_state() {
+            GameState::Win(winner) if *winner == id => Some(WIN_SCORE - ply),
+            GameState::Win(_) => Some(-(WIN_SCORE - ply)),
+            GameState::Draw => Some(0),
+            GameState::InProgress if depth == 0 => {
+                let limit = WIN_SCORE - MAX_PLIES - 1;
+                let score = self.rules.evaluate(board, id, self.weights);
+                Some(score.clamp(-limit, limit))
+            }
+            GameState::InProgress => None,
+        }
+    }
+
+    /// Score of `board` for `root`, who maximizes while everyone else minimizes.
+    #[allow(clippy::too_many_arguments)]
+    fn paranoid(
+        &mut self,
+        board: &Board,
+        depth: usize,
+        ply: i32,
+        root: PlayerId,
+        mut alpha: i32,
+        mut beta: i32,
+        pv: &mut Vec<Play>,
+    ) -> i32 {
+        self.nodes += 1;
+        pv.clear();
+
+        if self.should_stop() {
+            return 0;
+        }
+        if let Some(score) = self.leaf_score(board, depth, ply, root) {
             return score;
         }
 
-        let mut max_score = i32::MIN + 1;
+        let maximizing = board.get_current_player().id == root;
+        let mut best = if maximizing { -INFINITY } else { INFINITY };
+        let mut child_pv = Vec::new();
 
-        for col in 0..COLS {
+        for play in self.rules.legal_plays(board) {
             let mut temp_board = board.clone();
-            if let Some(_) = temp_board.play_move(col) {
-                let raw_score = self.negamax(
+            if self.rules.apply(&mut temp_board, play).is_some() {
+                let score = self.paranoid(
                     &temp_board,
                     depth - 1,
-                    -beta,
-                    -alpha,
-                    &temp_board.get_current_player(),
+                    ply + 1,
+                    root,
+                    alpha,
+                    beta,
+                    &mut child_pv,
                 );
+                if self.aborted {
+                    return 0;
+                }
 
-                let score = if raw_score == i32::MIN {
-                    i32::MIN + 1
+                let better = if maximizing {
+                    score > best
                 } else {
-                    -raw_score
+                    score < best
                 };
-
-                max_score = max_score.max(score);
-                alpha = alpha.max(score);
+                if better {
+                    best = score;
+                    pv.clear();
+                    pv.push(play);
+                    pv.extend_from_slice(&child_pv);
+                }
+                if maximizing {
+                    alpha = alpha.max(score);
+                } else {
+                    beta = beta.min(score);
+                }
 
                 if alpha >= beta {
-                    break; // β cut-off
+                    break;
+                }
+            }
+        }
+        best
+    }
+
+    /**
+     * Scores of `board` for every player, by `PlayerId::index`. The player to
+     * move picks whichever play scores best for them, whatever it does to
+     * the others.
+     */
+    fn max_n(&mut self, board: &Board, depth: usize, ply: i32, pv: &mut Vec<Play>) -> [i32; 4] {
+        self.nodes += 1;
+        pv.clear();
+
+        let mut scores = [0; 4];
+        if self.should_stop() {
+            return scores;
+        }
+        if board.get_game_state() != &GameState::InProgress || depth == 0 {
+            for player in board.get_players() {
+                scores[player.id.index()] =
+                    self.leaf_score(board, depth, ply, player.id).unwrap_or(0);
+            }
+            return scores;
+        }
+
+        let mover = board.get_current_player().id.index();
+        let mut best: Option<[i32; 4]> = None;
+        let mut child_pv = Vec::new();
+
+        for play in self.rules.legal_plays(board) {
+            let mut temp_board = board.clone();
+            if self.rules.apply(&mut temp_board, play).is_some() {
+                let child = self.max_n(&temp_board, depth - 1, ply + 1, &mut child_pv);
+                if self.aborted {
+                    return scores;
+                }
+
+                if best.is_none_or(|best| child[mover] > best[mover]) {
+                    best = Some(child);
+                    pv.clear();
+                    pv.push(play);
+                    pv.extend_from_slice(&child_pv);
                 }
             }
         }
-        return max_score;
+        best.unwrap_or(scores)
     }
 }
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::game::COLS;
+    use crate::player::PlayerType;
+
+    #[test]
+    fn test_proven_range_covers_big_boards() {
+        // A win on the last cell of the biggest board is still proven, deeper searches are cut
+        let cells = BoardSize::MAX_SIDE * BoardSize::MAX_SIDE;
+        assert_eq!(plies_to_end(WIN_SCORE - cells as i32), Some(cells));
+        assert_eq!(depth_limit(usize::MAX), cells);
+
+        // A 20 by 20 board fills up well past the 42 plies of the standard one
+        let mut board = Board::new_human_vs_human().with_size("20x20".parse().unwrap());
+        for col in [0, 1, 0, 1, 0, 1] {
+            board.play_move(col);
+        }
+        let ai = AI {
+            depth: 2,
+            ..AI::default()
+        };
+        assert_eq!(ai.analyse(&board).score, WIN_SCORE - 1);
+        assert!(!is_proven(board.evaluate_side_to_move()));
+    }
 
     #[test]
     fn test_best_move_basic() {
@@ -109,7 +872,10 @@ mod tests {
         board.play_move(0); // X
         board.play_move(1); // O
 
-        let ai = AI { depth: 5 };
+        let ai = AI {
+            depth: 5,
+            ..AI::default()
+        };
         let best_col = ai.best_move(&board);
 
         assert!(best_col.is_some()); // Should return a valid column
@@ -126,9 +892,190 @@ mod tests {
         board.play_move(4); // O
         board.play_move(2); // X
 
-        let ai = AI { depth: 5 };
+        let ai = AI {
+            depth: 5,
+            ..AI::default()
+        };
         let best = ai.best_move(&board);
 
         assert_eq!(best, Some(3));
     }
+
+    #[test]
+    fn test_takes_fastest_win() {
+        let mut board = Board::new_human_vs_human();
+        for col in [0, 6, 1, 6, 2, 5] {
+            board.play_move(col);
+        }
+
+        let ai = AI {
+            depth: 5,
+            ..AI::default()
+        };
+        let result = ai.analyse(&board);
+
+        assert_eq!(result.best_move, Some(Play::drop(3)));
+        assert_eq!(result.score, WIN_SCORE - 1);
+        assert!(result.proven);
+        assert_eq!(plies_to_end(result.score), Some(1));
+    }
+
+    #[test]
+    fn test_proven_loss() {
+        // X X X in the middle of the bottom row, O cannot block both ends
+        let mut board = Board::new_human_vs_human();
+        for col in [1, 6, 2, 6, 3] {
+            board.play_move(col);
+        }
+
+        let ai = AI {
+            depth: 6,
+            ..AI::default()
+        };
+        let stop = AtomicBool::new(false);
+        let mut depths = Vec::new();
+        let info = ai.search(&board, SearchLimits::default(), &stop, |info| {
+            depths.push(info.depth)
+        });
+
+        assert_eq!(info.score, -(WIN_SCORE - 2));
+        assert!(info.result().proven);
+        assert!(info.best_move.is_some());
+        // Nothing left to find once the loss is proven
+        assert_eq!(depths, vec![1, 2]);
+        assert!(!is_proven(ai.analyse(&Board::new_human_vs_human()).score));
+    }
+
+    #[test]
+    fn test_parallel_search_matches_single_thread() {
+        let single = AI {
+            depth: 6,
+            ..AI::default()
+        };
+        let parallel = AI {
+            threads: 4,
+            deterministic: true,
+            ..single.clone()
+        };
+        let fast = AI {
+            deterministic: false,
+            ..parallel.clone()
+        };
+
+        for moves in [vec![], vec![3, 3, 2], vec![0, 6, 1, 6, 2, 5]] {
+            let mut board = Board::new_human_vs_human();
+            for col in moves {
+                board.play_move(col);
+            }
+
+            let expected = single.analyse(&board);
+            assert_eq!(parallel.analyse(&board), expected);
+            // Sharing bounds may pick another move, but never a worse one
+            assert_eq!(fast.analyse(&board).score, expected.score);
+        }
+    }
+
+    #[test]
+    fn test_parallel_search_can_be_stopped() {
+        let ai = AI {
+            depth: 42,
+            threads: 4,
+            ..AI::default()
+        };
+        let stop = AtomicBool::new(false);
+        let limits = SearchLimits {
+            depth: None,
+            movetime: Some(Duration::from_millis(50)),
+        };
+
+        let started = Instant::now();
+        let info = ai.search(&Board::new_human_vs_human(), limits, &stop, |_| {});
+
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(info.best_move.is_some());
+        assert!(info.nodes > 0);
+    }
+
+    #[test]
+    fn test_search_reports_each_depth() {
+        let board = Board::new_human_vs_human();
+        let ai = AI {
+            depth: 4,
+            ..AI::default()
+        };
+        let stop = AtomicBool::new(false);
+
+        let mut depths = Vec::new();
+        let info = ai.search(&board, SearchLimits::default(), &stop, |info| {
+            depths.push(info.depth)
+        });
+
+        assert_eq!(depths, vec![1, 2, 3, 4]);
+        assert_eq!(info.depth, 4);
+        assert_eq!(info.pv.len(), 4);
+        assert_eq!(info.best_move, Some(info.pv[0]));
+        assert!(info.nodes > 0);
+    }
+
+    #[test]
+    fn test_search_stopped_still_returns_move() {
+        let board = Board::new_human_vs_human();
+        let ai = AI {
+            depth: 10,
+            ..AI::default()
+        };
+        let stop = AtomicBool::new(true);
+
+        let info = ai.search(&board, SearchLimits::default(), &stop, |_| {});
+
+        assert_eq!(info.depth, 0);
+        assert!(info.best_move.is_some());
+    }
+
+    #[test]
+    fn test_search_respects_movetime() {
+        let board = Board::new_human_vs_human();
+        let ai = AI {
+            depth: 42,
+            ..AI::default()
+        };
+        let stop = AtomicBool::new(false);
+        let limits = SearchLimits {
+            depth: None,
+            movetime: Some(Duration::from_millis(50)),
+        };
+
+        let started = Instant::now();
+        let info = ai.search(&board, limits, &stop, |_| {});
+
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(info.depth < 42);
+        assert!(info.best_move.is_some());
+    }
+
+    fn three_player_board(moves: &[usize]) -> Board {
+        let mut board = Board::new_multiplayer(&vec![PlayerType::Human; 3]);
+        for &col in moves {
+            board.play_move(col);
+        }
+        board
+    }
+
+    #[test]
+    fn test_multiplayer_search() {
+        // Player 1 has 0, 1 and 2 along the bottom
+        let winning = three_player_board(&[0, 5, 6, 1, 5, 6, 2, 4, 4]);
+        // Player 2 has 0, 1 and 2 along the bottom, and moves after Player 1
+        let blocking = three_player_board(&[6, 0, 5, 6, 1, 5, 4, 2, 4]);
+
+        for multi_search in [MultiSearch::Paranoid, MultiSearch::MaxN] {
+            let ai = AI {
+                depth: 3,
+                multi_search,
+                ..AI::default()
+            };
+            assert_eq!(ai.best_move(&winning), Some(3), "{:?}", multi_search);
+            assert_eq!(ai.best_move(&blocking), Some(3), "{:?}", multi_search);
+        }
+    }
 }
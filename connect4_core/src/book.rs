@@ -0,0 +1,408 @@
+use crate::ai::{is_proven, AI, WIN_SCORE};
+use crate::format::FormatError;
+use crate::game::{Board, GameState, COLS};
+use crate::protocol::{board_from_moves, format_moves, parse_moves, ProtocolError};
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::{fmt, fs, io};
+
+/// A move from a book position, with its value for the player making it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BookMove {
+    pub col: usize,
+    pub value: i32,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+struct BookEntry {
+    moves: Vec<usize>,
+    book_moves: Vec<BookMove>,
+}
+
+/**
+ * Precomputed moves for the first few plies. The file has one position per
+ * line: the columns played to reach it (`-` for the empty board) followed by
+ * `col:value` for each move, e.g.
+ *
+ * ```text
+ * -  3:120 2:40 4:40
+ * 33 3:35 2:10
+ * ```
+ *
+ * Positions are looked up by `Board::position_key`, so transpositions of a
+ * book line are found too.
+ */
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct OpeningBook {
+    entries: HashMap<u64, BookEntry>,
+}
+
+/// How an `AI` uses its book.
+#[derive(Clone)]
+pub struct BookSettings {
+    pub book: Arc<OpeningBook>,
+    /// Only positions with fewer discs than this are looked up.
+    pub max_plies: usize,
+    /**
+     * Moves valued within `variety` of the best are picked at random, the
+     * better ones more often. With 0 the best move is always played.
+     */
+    pub variety: i32,
+}
+
+// Books are shared and never change, so comparing where they live is enough
+impl PartialEq for BookSettings {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.book, &other.book)
+            && self.max_plies == other.max_plies
+            && self.variety == other.variety
+    }
+}
+
+impl fmt::Debug for BookSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BookSettings")
+            .field("positions", &self.book.len())
+            .field("max_plies", &self.max_plies)
+            .field("variety", &self.variety)
+            .finish()
+    }
+}
+
+impl OpeningBook {
+    pub fn new() -> Self {
+        OpeningBook::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Adds a position. `moves` must be legal from the empty board.
+    pub fn insert(
+        &mut self,
+        moves: &[usize],
+        book_moves: Vec<BookMove>,
+    ) -> Result<(), ProtocolError> {
+        let board = board_from_moves(moves)?;
+        let entry = BookEntry {
+            moves: moves.to_vec(),
+            book_moves,
+        };
+        self.entries.insert(board.position_key(), entry);
+        Ok(())
+    }
+
+    pub fn lookup(&self, board: &Board) -> Option<&[BookMove]> {
+        self.entries
+            .get(&board.position_key())
+            .map(|entry| entry.book_moves.as_slice())
+    }
+
+    pub fn best_move(&self, board: &Board) -> Option<BookMove> {
+        self.lookup(board)?
+            .iter()
+            .copied()
+            .max_by_key(|book_move| book_move.value)
+    }
+
+    /// Picks a move as described on `BookSettings::variety`.
+    pub fn choose_move(&self, board: &Board, variety: i32, rng: &mut impl Rng) -> Option<BookMove> {
+        let best = self.best_move(board)?;
+        let variety = variety.max(0);
+
+        let candidates: Vec<(BookMove, i64)> = self
+            .lookup(board)?
+            .iter()
+            .filter(|book_move| best.value - book_move.value <= variety)
+            .map(|&book_move| {
+                (
+                    book_move,
+                    (variety - (best.value - book_move.value)) as i64 + 1,
+                )
+            })
+            .collect();
+
+        let total: i64 = candidates.iter().map(|(_, weight)| weight).sum();
+        let mut pick = rng.gen_range(0..total);
+        for (book_move, weight) in candidates {
+            if pick < weight {
+                return Some(book_move);
+            }
+            pick -= weight;
+        }
+        unreachable!("pick is below the total weight")
+    }
+
+    pub fn load(path: &str) -> io::Result<OpeningBook> {
+        Ok(fs::read_to_string(path)?.parse()?)
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    /**
+     * Builds a book of every position up to `plies` discs by searching each
+     * move with `ai`. `on_position` is called with the number of positions
+     * done and the total, as deep searches take a while.
+     */
+    pub fn generate(
+        plies: usize,
+        ai: AI,
+        mut on_position: impl FnMut(usize, usize),
+    ) -> OpeningBook {
+        let stop = AtomicBool::new(false);
+
+        // Collect positions breadth first, keeping one line for each transposition
+        let mut positions: Vec<(Vec<usize>, Board)> = Vec::new();
+        let mut seen = HashSet::new();
+        let mut frontier = vec![(Vec::new(), Board::new_human_vs_human())];
+        for ply in 0..plies {
+            let mut next = Vec::new();
+            for (moves, board) in frontier {
+                if *board.get_game_state() != GameState::InProgress {
+                    continue;
+                }
+                if ply + 1 < plies {
+                    for col in 0..COLS {
+                        let mut child = board.clone();
+                        if child.play_move(col).is_some() && seen.insert(child.position_key()) {
+                            let mut line = moves.clone();
+                            line.push(col);
+                            next.push((line, child));
+                        }
+                    }
+                }
+                positions.push((moves, board));
+            }
+            frontier = next;
+        }
+
+        let mut book = OpeningBook::new();
+        let total = positions.len();
+        for (done, (moves, board)) in positions.into_iter().enumerate() {
+            let mut book_moves = Vec::new();
+            for col in 0..COLS {
+                let mut child = board.clone();
+                if child.play_move(col).is_none() {
+                    continue;
+                }
+                let value = match child.get_game_state() {
+                    GameState::InProgress => {
+                        let score = -ai.search(&child, Default::default(), &stop, |_| {}).score;
+                        // Wins and losses are one ply further away from here
+                        if is_proven(score) {
+                            score - score.signum()
+                        } else {
+                            score
+                        }
+                    }
+                    GameState::Win(_) => WIN_SCORE - 1,
+                    GameState::Draw => 0,
+                };
+                book_moves.push(BookMove { col, value });
+            }
+            book.insert(&moves, book_moves)
+                .expect("generated lines are legal");
+            on_position(done + 1, total);
+        }
+        book
+    }
+}
+
+impl std::str::FromStr for OpeningBook {
+    type Err = FormatError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut book = OpeningBook::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let mut tokens = line.split_whitespace();
+            let Some(moves) = tokens.next() else {
+                continue;
+            };
+            let error = |message: String| FormatError::at(number, message);
+
+            let moves = if moves == "-" {
+                Vec::new()
+            } else {
+                parse_moves(moves).map_err(|err| error(err.0))?
+            };
+
+            let mut book_moves = Vec::new();
+            for token in tokens {
+                let parsed = token
+                    .split_once(':')
+                    .and_then(|(col, value)| Some((col.parse().ok()?, value.parse().ok()?)));
+                match parsed {
+                    Some((col, value)) if col < COLS => book_moves.push(BookMove { col, value }),
+                    _ => return Err(error(format!("invalid book move: {}", token))),
+                }
+            }
+
+            book.insert(&moves, book_moves)
+                .map_err(|err| error(err.0))?;
+        }
+
+        Ok(book)
+    }
+}
+
+impl fmt::Display for OpeningBook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Shortest lines first so the file reads like a tree
+        let mut entries: Vec<&BookEntry> = self.entries.values().collect();
+        entries.sort_by(|a, b| (a.moves.len(), &a.moves).cmp(&(b.moves.len(), &b.moves)));
+
+        for entry in entries {
+            if entry.moves.is_empty() {
+                write!(f, "-")?;
+            } else {
+                write!(f, "{}", format_moves(&entry.moves))?;
+            }
+            for book_move in &entry.book_moves {
+                write!(f, " {}:{}", book_move.col, book_move.value)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ROWS;
+    use crate::player::PlayerId;
+
+    const BOOK: &str = "
+        # Opening moves
+        -  3:120 2:40 4:40 0:-50
+        012 3:35
+    ";
+
+    #[test]
+    fn test_parse_and_lookup() {
+        let book: OpeningBook = BOOK.parse().unwrap();
+        assert_eq!(book.len(), 2);
+
+        let board = Board::new_human_vs_human();
+        assert_eq!(
+            book.best_move(&board),
+            Some(BookMove { col: 3, value: 120 })
+        );
+
+        // "210" reaches the same position as "012"
+        let transposed = board_from_moves(&[2, 1, 0]).unwrap();
+        assert_eq!(book.best_move(&transposed).map(|m| m.col), Some(3));
+
+        assert!(book.lookup(&board_from_moves(&[3]).unwrap()).is_none());
+        assert!("- 9:10".parse::<OpeningBook>().is_err());
+        assert!("0000000 3:10".parse::<OpeningBook>().is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let book: OpeningBook = BOOK.parse().unwrap();
+        let text = book.to_string();
+
+        assert_eq!(text, "- 3:120 2:40 4:40 0:-50\n012 3:35\n");
+        assert_eq!(text.parse(), Ok(book));
+    }
+
+    #[test]
+    fn test_variety() {
+        let book: OpeningBook = BOOK.parse().unwrap();
+        let board = Board::new_human_vs_human();
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..20 {
+            assert_eq!(book.choose_move(&board, 0, &mut rng).unwrap().col, 3);
+            let col = book.choose_move(&board, 80, &mut rng).unwrap().col;
+            assert!([2, 3, 4].contains(&col));
+        }
+    }
+
+    #[test]
+    fn test_generate() {
+        let mut progress = Vec::new();
+        let book = OpeningBook::generate(
+            2,
+            AI {
+                depth: 2,
+                ..AI::default()
+            },
+            |done, total| progress.push((done, total)),
+        );
+
+        // The empty board and the seven positions after one move
+        assert_eq!(book.len(), 8);
+        assert_eq!(progress.last(), Some(&(8, 8)));
+        assert_eq!(
+            book.lookup(&Board::new_human_vs_human()).unwrap().len(),
+            COLS
+        );
+    }
+
+    #[test]
+    fn test_ai_plays_from_book() {
+        let book = Arc::new("- 5:10".parse::<OpeningBook>().unwrap());
+        let ai = AI {
+            depth: 4,
+            book: Some(BookSettings {
+                book,
+                max_plies: 4,
+                variety: 0,
+            }),
+            ..AI::default()
+        };
+
+        assert_eq!(ai.best_move(&Board::new_human_vs_human()), Some(5));
+        // Out of book, it searches as usual
+        assert!(ai.best_move(&board_from_moves(&[3]).unwrap()).is_some());
+
+        // A book move into a full column is searched past too
+        let full = [0; ROWS];
+        let ai = AI {
+            book: Some(BookSettings {
+                book: Arc::new(format!("{} 0:10", format_moves(&full)).parse().unwrap()),
+                max_plies: 8,
+                variety: 0,
+            }),
+            ..ai
+        };
+        let best = ai.best_move(&board_from_moves(&full).unwrap());
+        assert!(best.is_some_and(|col| col != 0));
+    }
+
+    #[test]
+    fn test_book_only_when_player_one_started() {
+        let ai = AI {
+            depth: 2,
+            book: Some(BookSettings {
+                book: Arc::new("- 5:10\n3 0:10".parse().unwrap()),
+                max_plies: 4,
+                variety: 0,
+            }),
+            ..AI::default()
+        };
+        let board = Board::new_human_vs_human();
+        assert!(ai.in_book(&board));
+
+        // The same discs with Player 2 to move are not the position in the book
+        let mut board = board
+            .with_turn_order(&[PlayerId::Two, PlayerId::One])
+            .unwrap();
+        assert!(!ai.in_book(&board));
+        board.play_move(3);
+        assert!(!ai.in_book(&board));
+    }
+}
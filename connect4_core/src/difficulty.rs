@@ -0,0 +1,205 @@
+use crate::ai::AI;
+use crate::eval::{EvalMode, EvalWeights};
+use crate::protocol::ProtocolError;
+use std::str::FromStr;
+use std::{fmt, thread};
+
+/**
+ * How well the AI plays. Weaker levels search less deeply, pick among the
+ * good moves at random rather than always the best, now and then play a
+ * random move, and at the bottom do not see threes coming until they can be
+ * completed on the next move.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Difficulty {
+    Beginner,
+    Easy,
+    Medium,
+    Hard,
+    Expert,
+    /// The deepest search, on every core. Strong, but not a solver: it can still lose.
+    Master,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 6] = [
+        Difficulty::Beginner,
+        Difficulty::Easy,
+        Difficulty::Medium,
+        Difficulty::Hard,
+        Difficulty::Expert,
+        Difficulty::Master,
+    ];
+
+    pub fn ai(self) -> AI {
+        let (depth, temperature, blunder_chance, misses_long_threats) = match self {
+            Difficulty::Beginner => (2, 3000.0, 0.2, true),
+            Difficulty::Easy => (3, 800.0, 0.05, true),
+            Difficulty::Medium => (4, 500.0, 0.03, false),
+            Difficulty::Hard => (6, 100.0, 0.0, false),
+            Difficulty::Expert => (8, 0.0, 0.0, false),
+            Difficulty::Master => (12, 0.0, 0.0, false),
+        };
+
+        let mut weights = EvalWeights::default();
+        if misses_long_threats {
+            weights.mode = EvalMode::Careless;
+        }
+
+        AI {
+            depth,
+            weights,
+            temperature,
+            blunder_chance,
+            // The deepest level needs every core to answer in reasonable time
+            threads: match self {
+                Difficulty::Master => thread::available_parallelism().map_or(1, |n| n.get()),
+                _ => 1,
+            },
+            ..AI::default()
+        }
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = ProtocolError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Difficulty::ALL
+            .into_iter()
+            .find(|level| level.to_string() == text.to_lowercase())
+            .ok_or_else(|| ProtocolError(format!("unknown difficulty: {}", text)))
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Difficulty::Beginner => "beginner",
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+            Difficulty::Expert => "expert",
+            Difficulty::Master => "master",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Board, COLS};
+    use crate::protocol::board_from_moves;
+    use crate::tournament::{game_seed, play_pairing, Entrant, Record};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_parse() {
+        for level in Difficulty::ALL {
+            assert_eq!(level.to_string().parse(), Ok(level));
+        }
+        assert_eq!("Hard".parse(), Ok(Difficulty::Hard));
+        assert!("impossible".parse::<Difficulty>().is_err());
+    }
+
+    #[test]
+    fn test_levels_get_stronger() {
+        let depths: Vec<usize> = Difficulty::ALL
+            .iter()
+            .map(|level| level.ai().depth)
+            .collect();
+        assert!(depths.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let beginner = Difficulty::Beginner.ai();
+        assert!(beginner.temperature > 0.0 && beginner.blunder_chance > 0.0);
+        assert_eq!(Difficulty::Expert.ai().temperature, 0.0);
+    }
+
+    #[test]
+    fn test_random_levels_still_take_wins() {
+        // X X X _ on the bottom row: short of a blunder, even a beginner finishes it
+        let board = board_from_moves(&[0, 6, 1, 6, 2, 5]).unwrap();
+        let ai = AI {
+            blunder_chance: 0.0,
+            ..Difficulty::Beginner.ai()
+        };
+        for _ in 0..10 {
+            assert_eq!(ai.best_move(&board), Some(3));
+        }
+
+        // And plays varied moves from the start
+        let board = Board::new_human_vs_human();
+        let moves: HashSet<_> = (0..30).filter_map(|_| ai.best_move(&board)).collect();
+        assert!(moves.len() > 1);
+    }
+
+    /// Games between two levels from `openings`, each side starting in turn, for `stronger`.
+    fn play_levels(stronger: Difficulty, weaker: Difficulty, openings: &[[usize; 2]]) -> Record {
+        // Parallel search is only repeatable when deterministic
+        let entrant = |level: Difficulty| {
+            Entrant::ai(AI {
+                deterministic: true,
+                ..level.ai()
+            })
+        };
+        let (stronger, weaker) = (entrant(stronger), entrant(weaker));
+        let seed = |game, entrant| game_seed(2, game, entrant);
+
+        let mut record = Record::default();
+        for (game, opening) in openings.iter().enumerate() {
+            let game = game * 2;
+            let seeds = [seed(game, 0), seed(game, 1)];
+            record.add(play_pairing(&stronger, &weaker, opening, seeds));
+            let seeds = [seed(game + 1, 1), seed(game + 1, 0)];
+            record.add(play_pairing(&weaker, &stronger, opening, seeds).reversed());
+        }
+        record
+    }
+
+    fn assert_levels_beat_the_one_below(levels: &[Difficulty], openings: &[[usize; 2]]) {
+        for pair in levels.windows(2) {
+            let record = play_levels(pair[1], pair[0], openings);
+            assert!(
+                record.score() > 0.5,
+                "{} against {}: {}",
+                pair[1],
+                pair[0],
+                record
+            );
+        }
+    }
+
+    #[test]
+    fn test_each_level_beats_the_one_below() {
+        // Every fifth of the 49 two-disc openings
+        let openings: Vec<[usize; 2]> = (0..COLS * COLS)
+            .step_by(5)
+            .map(|index| [index / COLS, index % COLS])
+            .collect();
+        assert_levels_beat_the_one_below(&Difficulty::ALL[..3], &openings);
+    }
+
+    /// Minutes a game at the top, even in release: `cargo test --release -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_searching_levels_beat_the_one_below() {
+        assert_levels_beat_the_one_below(&Difficulty::ALL[2..], &[[3, 3], [2, 4]]);
+    }
+
+    #[test]
+    fn test_medium_beats_beginner() {
+        let medium = Entrant::ai(Difficulty::Medium.ai());
+        let beginner = Entrant::ai(Difficulty::Beginner.ai());
+        let seed = |game, entrant| game_seed(1, game, entrant);
+
+        let mut record = Record::default();
+        for game in (0..8).step_by(2) {
+            let seeds = [seed(game, 0), seed(game, 1)];
+            record.add(play_pairing(&medium, &beginner, &[], seeds));
+            let seeds = [seed(game + 1, 1), seed(game + 1, 0)];
+            record.add(play_pairing(&beginner, &medium, &[], seeds).reversed());
+        }
+        assert!(record.score() > 0.6, "{}", record);
+    }
+}
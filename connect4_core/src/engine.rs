@@ -0,0 +1,290 @@
+use crate::agent::{Agent, AgentError};
+use crate::ai::SearchLimits;
+use crate::game::{Board, MoveKind, Play};
+use crate::protocol::{Command, Response};
+use std::ffi::OsStr;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ExitStatus, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/**
+ * An engine running as a child process that speaks the text protocol in
+ * `protocol`, e.g. `connect4_engine` or an older build of it. Each move is
+ * requested with `go movetime`; an engine that overruns by more than
+ * `grace` is told to stop, and killed if it still does not answer.
+ */
+pub struct ExternalEngine {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    pub movetime: Duration,
+    pub grace: Duration,
+}
+
+impl ExternalEngine {
+    pub fn spawn<P, A>(program: P, args: &[A]) -> Result<Self, AgentError>
+    where
+        P: AsRef<OsStr>,
+        A: AsRef<OsStr>,
+    {
+        let name = program.as_ref().to_string_lossy().into_owned();
+        let mut child = std::process::Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| AgentError::Crashed(err.to_string()))?;
+
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        // Reading happens on its own thread so we can give up waiting on a hung engine
+        let (sender, lines) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = ExternalEngine {
+            name,
+            child,
+            stdin,
+            lines,
+            movetime: Duration::from_millis(1000),
+            grace: Duration::from_millis(1000),
+        };
+
+        engine.send(&Command::IsReady)?;
+        let deadline = Instant::now() + engine.grace;
+        loop {
+            if engine.receive(deadline)? == Response::ReadyOk {
+                return Ok(engine);
+            }
+        }
+    }
+
+    pub fn with_movetime(mut self, movetime: Duration) -> Self {
+        self.movetime = movetime;
+        self
+    }
+
+    pub fn with_grace(mut self, grace: Duration) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    fn send(&mut self, command: &Command) -> Result<(), AgentError> {
+        writeln!(self.stdin, "{}", command)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|err| AgentError::Crashed(err.to_string()))
+    }
+
+    /// Waits for the next line we understand. Anything else the engine prints is ignored.
+    fn receive(&mut self, deadline: Instant) -> Result<Response, AgentError> {
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(timeout) {
+                Ok(line) => {
+                    if let Ok(response) = line.parse() {
+                        return Ok(response);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => return Err(AgentError::Timeout),
+                Err(RecvTimeoutError::Disconnected) => {
+                    // It closed its output, so it should be on its way out
+                    let reason = match self.wait_for_exit(Instant::now() + self.grace) {
+                        Some(status) => status.to_string(),
+                        None => {
+                            self.kill();
+                            "closed its output".to_string()
+                        }
+                    };
+                    return Err(AgentError::Crashed(reason));
+                }
+            }
+        }
+    }
+
+    /// Gives the engine until `deadline` to exit, without blocking past it.
+    fn wait_for_exit(&mut self, deadline: Instant) -> Option<ExitStatus> {
+        loop {
+            if let Ok(Some(status)) = self.child.try_wait() {
+                return Some(status);
+            }
+            if Instant::now() >= deadline {
+                return None;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn kill(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+
+    fn wait_for_best_move(&mut self, deadline: Instant) -> Result<Option<Play>, AgentError> {
+        loop {
+            if let Response::BestMove(play) = self.receive(deadline)? {
+                return Ok(play);
+            }
+        }
+    }
+}
+
+impl Agent for ExternalEngine {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn choose_move(&mut self, board: &Board, plays: &[Play]) -> Result<Play, AgentError> {
+        // Positions are sent as the columns dropped in since the empty board
+        let moves: Option<Vec<usize>> = plays
+            .iter()
+            .map(|play| (play.kind == MoveKind::Drop).then_some(play.col))
+            .collect();
+        let moves = match moves {
+            Some(moves) if board.is_classic() && board.moves_played() == moves.len() => moves,
+            _ => {
+                return Err(AgentError::Unsupported(
+                    "anything but classic Connect 4".into(),
+                ))
+            }
+        };
+
+        let limits = SearchLimits {
+            depth: None,
+            movetime: Some(self.movetime),
+        };
+        self.send(&Command::Position(moves))?;
+        self.send(&Command::Go(limits))?;
+
+        let deadline = Instant::now() + self.movetime + self.grace;
+        let best_move = match self.wait_for_best_move(deadline) {
+            Err(AgentError::Timeout) => {
+                // Out of time: ask for its move anyway, but the game is already lost
+                self.send(&Command::Stop)?;
+                if self
+                    .wait_for_best_move(Instant::now() + self.grace)
+                    .is_err()
+                {
+                    self.kill();
+                }
+                return Err(AgentError::Timeout);
+            }
+            best_move => best_move?,
+        };
+
+        best_move.ok_or(AgentError::NoMove)
+    }
+}
+
+impl Drop for ExternalEngine {
+    fn drop(&mut self) {
+        let _ = self.send(&Command::Quit);
+        if self.wait_for_exit(Instant::now() + self.grace).is_none() {
+            self.kill();
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::agent::play_agents;
+    use crate::ai::AI;
+    use crate::game::Variant;
+    use crate::player::PlayerId;
+    use crate::protocol::board_from_moves;
+
+    /// A tiny engine written in shell. `reply` is run for every `go`.
+    fn stub_engine(reply: &str) -> Result<ExternalEngine, AgentError> {
+        let script = format!(
+            "while read line; do case \"$line\" in \
+                isready) echo readyok;; \
+                go*) {};; \
+                quit) exit 0;; \
+             esac; done",
+            reply
+        );
+        Ok(ExternalEngine::spawn("sh", &["-c", &script])?
+            .with_movetime(Duration::from_millis(100))
+            .with_grace(Duration::from_millis(200)))
+    }
+
+    #[test]
+    fn test_stub_engine_moves() {
+        let mut engine =
+            stub_engine("echo info depth 1 score 0 nodes 1 pv 3; echo bestmove 3").unwrap();
+        let board = Board::new_human_vs_human();
+        assert_eq!(engine.choose_move(&board, &[]), Ok(Play::drop(3)));
+
+        let plays = [Play::drop(3), Play::drop(3)];
+        let board = board_from_moves(&[3, 3]).unwrap();
+        assert_eq!(engine.choose_move(&board, &plays), Ok(Play::drop(3)));
+    }
+
+    #[test]
+    fn test_engines_only_play_classic() {
+        let mut engine = stub_engine("echo bestmove 3").unwrap();
+        let board = Board::new_human_vs_human().with_variant(Variant::PopOut);
+
+        assert!(matches!(
+            engine.choose_move(&board, &[]),
+            Err(AgentError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_slow_engine_times_out() {
+        let mut engine = stub_engine("sleep 1; echo bestmove 3").unwrap();
+        let board = Board::new_human_vs_human();
+
+        assert_eq!(engine.choose_move(&board, &[]), Err(AgentError::Timeout));
+    }
+
+    #[test]
+    fn test_crash_forfeits_the_game() {
+        let mut engine = stub_engine("exit 3").unwrap();
+        let mut ai = AI {
+            depth: 2,
+            ..AI::default()
+        };
+        let mut board = Board::new_human_vs_human();
+
+        let outcome = play_agents(&mut board, [&mut engine, &mut ai]);
+
+        match outcome.forfeit {
+            Some((PlayerId::One, AgentError::Crashed(_))) => {}
+            forfeit => panic!("unexpected forfeit {:?}", forfeit),
+        }
+        assert_eq!(outcome.winner(), Some(PlayerId::Two));
+    }
+
+    #[test]
+    fn test_closed_output_does_not_hang() {
+        // Stops talking but never exits
+        let mut engine = stub_engine("exec >&-; sleep 5").unwrap();
+        let board = Board::new_human_vs_human();
+
+        let start = Instant::now();
+        assert!(matches!(
+            engine.choose_move(&board, &[]),
+            Err(AgentError::Crashed(_))
+        ));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_missing_executable() {
+        assert!(ExternalEngine::spawn("./no-such-engine", &["--threads", "1"]).is_err());
+    }
+}
@@ -0,0 +1,298 @@
+use crate::format::FormatError;
+use crate::game::COLS;
+use crate::protocol::ProtocolError;
+use std::str::FromStr;
+use std::{fmt, fs, io};
+
+/// Names of the weights other than `center`, as written in files.
+const NAMES: [&str; 13] = [
+    "two",
+    "three",
+    "four",
+    "opponent_two",
+    "opponent_three",
+    "opponent_four",
+    "odd_threat",
+    "even_threat",
+    "playable_threat",
+    "good_threat",
+    "bad_threat",
+    "capture",
+    "power_up",
+];
+
+/// What `Board::evaluate` looks at besides the center columns.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum EvalMode {
+    /// Only lines of four cells, scored by how full they are.
+    #[default]
+    Windows,
+    /// The windows plus every threat, see `threats::threat_score`.
+    Threats,
+    /**
+     * The windows, but blind to the opponents' threes, which are only seen
+     * once the search finds them completed. A handicap for the weaker
+     * difficulty levels. The score is no longer the same for both players
+     * with the sign flipped, so the search takes it from its own side.
+     */
+    Careless,
+}
+
+impl FromStr for EvalMode {
+    type Err = ProtocolError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "windows" => Ok(EvalMode::Windows),
+            "threats" => Ok(EvalMode::Threats),
+            "careless" => Ok(EvalMode::Careless),
+            _ => Err(ProtocolError(format!("unknown evaluation mode: {}", text))),
+        }
+    }
+}
+
+impl fmt::Display for EvalMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalMode::Windows => write!(f, "windows"),
+            EvalMode::Threats => write!(f, "threats"),
+            EvalMode::Careless => write!(f, "careless"),
+        }
+    }
+}
+
+/**
+ * The numbers behind `Board::evaluate`. Window scores apply to every line of
+ * four cells: `two` is two of our discs and two empty cells, `three` is three
+ * and one empty, `four` a completed line, and the `opponent_` versions the
+ * same for the other player (subtracted).
+ *
+ * Weights can be kept in a file of `name = value` lines, see `Display`.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct EvalWeights {
+    pub mode: EvalMode,
+    /// Bonus for each of our discs, per column.
+    pub center: [i32; COLS],
+    pub two: i32,
+    pub three: i32,
+    pub four: i32,
+    pub opponent_two: i32,
+    pub opponent_three: i32,
+    pub opponent_four: i32,
+    /**
+     * Added to `three` when the missing cell is on a row that suits its
+     * owner, see `threats::odd_player`: an odd row (1, 3, 5 up from the
+     * bottom of its column) for the player who gets the odd cells, or an
+     * even one for the other player. That is Player 1 and Player 2 when
+     * Player 1 starts on an empty board, but the other way round when
+     * Player 2 starts or a layout leaves an odd number of cells.
+     */
+    pub odd_threat: i32,
+    pub even_threat: i32,
+    /**
+     * Only used in `EvalMode::Threats`, for each empty cell that completes a
+     * four: one that can be played right away, or one stacked above empty
+     * cells on a row whose parity suits its owner or not.
+     */
+    pub playable_threat: i32,
+    pub good_threat: i32,
+    pub bad_threat: i32,
+    /**
+     * Only used by the variants that have them: each disc captured in Pop
+     * Ten, more than any shape on the board, and each power-up still in hand,
+     * less than a three, so they get used.
+     */
+    pub capture: i32,
+    pub power_up: i32,
+}
+
+impl Default for EvalWeights {
+    fn default() -> Self {
+        EvalWeights {
+            mode: EvalMode::Windows,
+            center: [0, 0, 0, 3, 0, 0, 0],
+            two: 500,
+            three: 5000,
+            four: 100000,
+            opponent_two: 0,
+            opponent_three: 4000,
+            opponent_four: 0,
+            odd_threat: 0,
+            even_threat: 0,
+            playable_threat: 3000,
+            good_threat: 2000,
+            bad_threat: 500,
+            capture: 5000,
+            power_up: 300,
+        }
+    }
+}
+
+impl EvalWeights {
+    pub fn load(path: &str) -> io::Result<EvalWeights> {
+        Ok(fs::read_to_string(path)?.parse()?)
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    /// Number of weights, as seen by `param_mut`.
+    pub const PARAMS: usize = COLS + NAMES.len();
+
+    /// Every weight by index, the center columns first, for tuning.
+    pub fn param_mut(&mut self, index: usize) -> &mut i32 {
+        if index < COLS {
+            &mut self.center[index]
+        } else {
+            self.field(NAMES[index - COLS]).expect("index below PARAMS")
+        }
+    }
+
+    fn field(&mut self, name: &str) -> Option<&mut i32> {
+        match name {
+            "two" => Some(&mut self.two),
+            "three" => Some(&mut self.three),
+            "four" => Some(&mut self.four),
+            "opponent_two" => Some(&mut self.opponent_two),
+            "opponent_three" => Some(&mut self.opponent_three),
+            "opponent_four" => Some(&mut self.opponent_four),
+            "odd_threat" => Some(&mut self.odd_threat),
+            "even_threat" => Some(&mut self.even_threat),
+            "playable_threat" => Some(&mut self.playable_threat),
+            "good_threat" => Some(&mut self.good_threat),
+            "bad_threat" => Some(&mut self.bad_threat),
+            "capture" => Some(&mut self.capture),
+            "power_up" => Some(&mut self.power_up),
+            _ => None,
+        }
+    }
+}
+
+/// Anything left out of the file keeps its default value.
+impl FromStr for EvalWeights {
+    type Err = FormatError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut weights = EvalWeights::default();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: String| FormatError::at(number, message);
+
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| error(format!("expected name = value: {}", line)))?;
+            let (name, value) = (name.trim(), value.trim());
+
+            let parse = |token: &str| {
+                token
+                    .parse::<i32>()
+                    .map_err(|_| error(format!("invalid value for {}: {}", name, token)))
+            };
+
+            if name == "mode" {
+                weights.mode = value.parse().map_err(|err: ProtocolError| error(err.0))?;
+            } else if name == "center" {
+                let values = value
+                    .split_whitespace()
+                    .map(parse)
+                    .collect::<Result<Vec<_>, _>>()?;
+                weights.center = values
+                    .try_into()
+                    .map_err(|_| error(format!("center needs {} values", COLS)))?;
+            } else {
+                let field = weights
+                    .field(name)
+                    .ok_or_else(|| error(format!("unknown weight: {}", name)))?;
+                *field = parse(value)?;
+            }
+        }
+
+        Ok(weights)
+    }
+}
+
+impl fmt::Display for EvalWeights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "mode = {}", self.mode)?;
+        write!(f, "center =")?;
+        for weight in self.center {
+            write!(f, " {}", weight)?;
+        }
+        writeln!(f)?;
+
+        let mut weights = *self;
+        for name in NAMES {
+            writeln!(f, "{} = {}", name, weights.field(name).unwrap())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Board;
+
+    #[test]
+    fn test_parse_weights() {
+        let weights: EvalWeights = "
+            # Favour the middle
+            center = 0 1 2 4 2 1 0
+            three = 6000
+        "
+        .parse()
+        .unwrap();
+
+        assert_eq!(weights.center, [0, 1, 2, 4, 2, 1, 0]);
+        assert_eq!(weights.three, 6000);
+        assert_eq!(weights.two, EvalWeights::default().two);
+
+        assert!("center = 1 2 3".parse::<EvalWeights>().is_err());
+        let err = "two = 1\nfive = 10".parse::<EvalWeights>().unwrap_err();
+        assert_eq!(err.to_string(), "line 2: unknown weight: five");
+        assert!("two = lots".parse::<EvalWeights>().is_err());
+        assert!("mode = vibes".parse::<EvalWeights>().is_err());
+        assert_eq!(
+            "mode = threats".parse::<EvalWeights>().unwrap().mode,
+            EvalMode::Threats
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let weights = EvalWeights {
+            mode: EvalMode::Threats,
+            odd_threat: 300,
+            capture: 7000,
+            ..EvalWeights::default()
+        };
+        assert_eq!(weights.to_string().parse(), Ok(weights));
+    }
+
+    #[test]
+    fn test_weights_change_evaluation() {
+        let mut board = Board::new_human_vs_human();
+        board.play_move(0);
+        let player = board.get_current_player().clone();
+        board.play_move(3);
+
+        let defaults = EvalWeights::default();
+        let edges = EvalWeights {
+            center: [10, 0, 0, 0, 0, 0, 10],
+            ..defaults
+        };
+
+        assert_eq!(
+            board.evaluate_with(&player, &defaults),
+            board.clone().evaluate(&player)
+        );
+        assert_eq!(board.evaluate_with(&player, &defaults), 3);
+        assert_eq!(board.evaluate_with(&player, &edges), 0);
+    }
+}
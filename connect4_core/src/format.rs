@@ -0,0 +1,48 @@
+use std::{fmt, io};
+
+/**
+ * A weights, book, positions or layout file that could not be read, with
+ * the line at fault when there is one. Unlike `ProtocolError` it comes from
+ * a file someone can open and fix, so it says where to look.
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub struct FormatError {
+    /// Counting from 1, `None` for a problem with the file as a whole.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl FormatError {
+    /// An error on the line at `index` from `lines().enumerate()`.
+    pub fn at(index: usize, message: impl Into<String>) -> Self {
+        FormatError {
+            line: Some(index + 1),
+            message: message.into(),
+        }
+    }
+
+    pub fn whole(message: impl Into<String>) -> Self {
+        FormatError {
+            line: None,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+/// So `load` functions can return `io::Result` for missing and malformed files alike.
+impl From<FormatError> for io::Error {
+    fn from(err: FormatError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
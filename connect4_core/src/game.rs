@@ -1,5 +1,19 @@
+use crate::agent::{Agent, AgentError};
 use crate::ai::AI;
+use crate::eval::{EvalMode, EvalWeights};
+use crate::format::FormatError;
+use crate::layout::{self, Layout};
 use crate::player::{Player, PlayerId, PlayerType};
+use crate::ponder::Ponder;
+use crate::protocol::{format_plays, ProtocolError};
+use crate::rules::{self, CustomRules, Inventory, PopTenState, Ruleset};
+use crate::spectator::Broadcaster;
+use crate::threats::{self, Threat};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 use std::{fmt, io};
 
 pub const ROWS: usize = 6;
@@ -10,13 +24,134 @@ pub const WINNING_LENGTH: usize = 4;
 pub enum GameState {
     InProgress,
     Draw,
-    Win(Player),
+    Win(PlayerId),
+}
+
+/// Who makes the first move of a game.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum FirstPlayer {
+    /// Whoever the board already has to move: Player 1, unless set otherwise.
+    #[default]
+    Keep,
+    PlayerOne,
+    PlayerTwo,
+    /// Drawn from the game's seed.
+    Random,
+    /// Player 1 in even-numbered games of a series and Player 2 in odd ones.
+    Alternate,
+}
+
+impl FirstPlayer {
+    /// Picks the first player of a game on `board`.
+    pub fn resolve(self, game_number: usize, board: &Board, rng: &mut impl Rng) -> PlayerId {
+        let players = board.players.len();
+        match self {
+            FirstPlayer::Keep => board.current_player,
+            FirstPlayer::PlayerOne => PlayerId::One,
+            FirstPlayer::PlayerTwo => PlayerId::Two,
+            FirstPlayer::Random => board.players[rng.gen_range(0..players)].id,
+            FirstPlayer::Alternate => PlayerId::ALL[game_number % players],
+        }
+    }
+}
+
+impl std::str::FromStr for FirstPlayer {
+    type Err = ProtocolError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "keep" => Ok(FirstPlayer::Keep),
+            "1" | "one" => Ok(FirstPlayer::PlayerOne),
+            "2" | "two" => Ok(FirstPlayer::PlayerTwo),
+            "random" => Ok(FirstPlayer::Random),
+            "alternate" => Ok(FirstPlayer::Alternate),
+            _ => Err(ProtocolError(format!("unknown first player: {}", text))),
+        }
+    }
+}
+
+/**
+ * Settings for one game of `play_game_with_config`. Every random choice in
+ * the game, who goes first and any the AI makes, comes from `seed`, so the
+ * same seed and players replay the same game.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GameConfig {
+    pub seed: u64,
+    pub first_player: FirstPlayer,
+    /// Which game of a series this is, counting from 0.
+    pub game_number: usize,
+}
+
+impl GameConfig {
+    /// A config with a fresh random seed.
+    pub fn random() -> Self {
+        GameConfig {
+            seed: rand::random(),
+            first_player: FirstPlayer::Keep,
+            game_number: 0,
+        }
+    }
+
+    pub fn rng(&self) -> StdRng {
+        StdRng::seed_from_u64(self.seed)
+    }
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig::random()
+    }
+}
+
+/// What happened in a game, with the seed needed to play it again.
+#[derive(Clone, PartialEq, Debug)]
+pub struct GameRecord {
+    pub seed: u64,
+    pub first_player: PlayerId,
+    pub moves: Vec<Play>,
+    pub state: GameState,
+    /// The final scores, for variants that keep them, see `Board::scores`.
+    pub scores: Option<Vec<usize>>,
+}
+
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let moves = if self.moves.is_empty() {
+            "-".to_string()
+        } else {
+            format_plays(&self.moves)
+        };
+        write!(
+            f,
+            "seed {}, Player {} first, moves {}: {}",
+            self.seed, self.first_player, moves, self.state
+        )?;
+        match &self.scores {
+            Some(scores) => write!(f, " ({})", format_scores(scores)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Scores in player order with each player's symbol, as in `X 10 - O 4`.
+pub(crate) fn format_scores(scores: &[usize]) -> String {
+    let scores: Vec<String> = PlayerId::ALL
+        .iter()
+        .zip(scores)
+        .map(|(id, score)| format!("{} {}", id.symbol(), score))
+        .collect();
+    scores.join(" - ")
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Cell {
     Empty,
-    Player(Player),
+    /// Out of play for the whole game, see `Layout`. Discs land on top of it.
+    Blocked,
+    /// A disc that counts for neither player, see `PowerUp::Wall`.
+    Wall,
+    Player(PlayerId),
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -25,25 +160,357 @@ pub struct Position {
     pub col: usize,
 }
 
+/// How big a board is and how many in a row win.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct BoardSize {
+    pub rows: usize,
+    pub cols: usize,
+    pub win_length: usize,
+}
+
+impl BoardSize {
+    /// The most rows or columns a board can have.
+    pub const MAX_SIDE: usize = 64;
+
+    /// Whether a line fits on the board, and the board is neither empty nor over `MAX_SIDE`.
+    pub fn is_valid(&self) -> bool {
+        let sides = 1..=BoardSize::MAX_SIDE;
+        // A line of one is not a game
+        sides.contains(&self.rows)
+            && sides.contains(&self.cols)
+            && self.win_length >= 2
+            && self.win_length <= self.rows.max(self.cols)
+    }
+}
+
+impl Default for BoardSize {
+    fn default() -> Self {
+        BoardSize {
+            rows: ROWS,
+            cols: COLS,
+            win_length: WINNING_LENGTH,
+        }
+    }
+}
+
+/// Written `COLSxROWS`, with `:N` on the end when N in a row is not 4, e.g. `9x7:5`.
+impl std::str::FromStr for BoardSize {
+    type Err = ProtocolError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || ProtocolError(format!("invalid board size: {}", text));
+        let (dims, win_length) = match text.split_once(':') {
+            Some((dims, n)) => (dims, n.parse().map_err(|_| invalid())?),
+            None => (text, WINNING_LENGTH),
+        };
+        let (cols, rows) = dims.split_once('x').ok_or_else(invalid)?;
+        let size = BoardSize {
+            rows: rows.parse().map_err(|_| invalid())?,
+            cols: cols.parse().map_err(|_| invalid())?,
+            win_length,
+        };
+        if !size.is_valid() {
+            return Err(invalid());
+        }
+        Ok(size)
+    }
+}
+
+impl fmt::Display for BoardSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.cols, self.rows)?;
+        if self.win_length != WINNING_LENGTH {
+            write!(f, ":{}", self.win_length)?;
+        }
+        Ok(())
+    }
+}
+
+/// The rules a `Board` is played by.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Variant {
+    #[default]
+    Classic,
+    /**
+     * Instead of dropping a disc, a player may pop one of their own discs
+     * out of the bottom row, and the rest of the column falls down. A full
+     * board is not a draw while the player to move can still pop, but the
+     * same position coming up a third time is. If a pop completes fours for
+     * both players, the one who popped wins.
+     */
+    PopOut,
+    /**
+     * The board is first filled up, with no wins for a four. Then each turn
+     * a player pops one of their own discs out of the bottom row. If it was
+     * part of a four they keep it and the turn passes, otherwise they put it
+     * back on top of a column. The first to capture `POP_TEN_TARGET` discs
+     * wins, and a player with nothing to pop misses their turn.
+     */
+    PopTen,
+    /// The classic rules, but rows and diagonals run off one side of the board and on at the other.
+    Cylinder,
+    /**
+     * The classic game, but every player also has one of each `PowerUp` to
+     * use once instead of a normal drop. A full board is only a draw once the
+     * player to move has nothing left to play.
+     */
+    PowerUps,
+    /**
+     * No gravity: a disc may go on any empty cell, like in tic-tac-toe or
+     * gomoku. Play it on a bigger board with a longer line to win, e.g.
+     * `15x15:5`.
+     */
+    FreePlacement,
+    /**
+     * For three or four players, played for places as in most multi-player
+     * Connect 4 sets: the first to make a line finishes first and wins,
+     * leaving their discs on the board, and the rest play on for second
+     * place and so on until one player is left or the board is full. The
+     * places are the scores, see `Board::finished`. With two players this is
+     * the same as the classic game.
+     */
+    Elimination,
+}
+
+impl Variant {
+    pub fn rules(self) -> &'static dyn Ruleset {
+        match self {
+            Variant::Classic | Variant::Cylinder => &rules::Classic,
+            Variant::PopOut => &rules::PopOut,
+            Variant::PopTen => &rules::PopTen,
+            Variant::Elimination => &rules::Elimination,
+            Variant::PowerUps => &rules::PowerUps,
+            Variant::FreePlacement => &rules::FreePlacement,
+        }
+    }
+
+    /// Whether only two can play, as popping only goes between two players.
+    pub fn is_two_player(self) -> bool {
+        matches!(self, Variant::PopOut | Variant::PopTen)
+    }
+
+    /// Whether discs fall to the bottom of their column.
+    pub fn has_gravity(self) -> bool {
+        self != Variant::FreePlacement
+    }
+
+    /// Whether lines wrap around from the rightmost column to the leftmost.
+    pub fn wraps(self) -> bool {
+        self == Variant::Cylinder
+    }
+}
+
+/// Every line of `size.win_length` cells on the board, wrapping around the sides if `wrap`.
+fn all_windows(size: BoardSize, wrap: bool) -> Vec<Vec<Position>> {
+    let BoardSize {
+        rows,
+        cols,
+        win_length,
+    } = size;
+    // A line may only go round the board once
+    let wrap = wrap && cols >= win_length;
+    let mut windows = Vec::new();
+
+    for row in 0..rows {
+        for col in 0..cols {
+            // Horizontal, vertical, diagonal / and diagonal \
+            for (dc, dr) in [(1, 0), (0, 1), (1, -1), (1, 1)] {
+                let cell = |i: usize| {
+                    let row = row
+                        .checked_add_signed(dr * i as isize)
+                        .filter(|&r| r < rows)?;
+                    let col = col + dc * i;
+                    if wrap {
+                        Some(Position {
+                            row,
+                            col: col % cols,
+                        })
+                    } else {
+                        (col < cols).then_some(Position { row, col })
+                    }
+                };
+                if let Some(window) = (0..win_length).map(cell).collect() {
+                    windows.push(window);
+                }
+            }
+        }
+    }
+
+    windows
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub enum MoveKind {
+    #[default]
+    Drop,
+    Pop,
+    PowerUp(PowerUp),
+    /// A disc put straight on the cell in this row of the play's column, see `Variant::FreePlacement`.
+    Place(usize),
+}
+
+/// A special disc each player may use once in `Variant::PowerUps`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum PowerUp {
+    /// Crushes every disc in the column and lands at the bottom as one of yours.
+    Anvil,
+    /// Blows the bottom disc out of a column, whoever it belongs to, and the rest falls.
+    Bomb,
+    /// A disc that belongs to neither player, so no line can go through it.
+    Wall,
+    /// A normal drop, after which you move again.
+    PlayTwice,
+}
+
+impl PowerUp {
+    pub const ALL: [PowerUp; 4] = [
+        PowerUp::Anvil,
+        PowerUp::Bomb,
+        PowerUp::Wall,
+        PowerUp::PlayTwice,
+    ];
+
+    /// Written before the column, e.g. `a3` for an anvil in column 3.
+    pub fn letter(self) -> char {
+        match self {
+            PowerUp::Anvil => 'a',
+            PowerUp::Bomb => 'b',
+            PowerUp::Wall => 'w',
+            PowerUp::PlayTwice => 't',
+        }
+    }
+}
+
+impl fmt::Display for PowerUp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PowerUp::Anvil => "anvil",
+            PowerUp::Bomb => "bomb",
+            PowerUp::Wall => "wall",
+            PowerUp::PlayTwice => "play twice",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/**
+ * What a player chooses to do on their turn, written `3` for a drop, `p3`
+ * for a pop, a power-up's letter and the column for a power-up, e.g. `a3`,
+ * and the column and row from the top for a placed disc, e.g. `3,2`.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub struct Play {
+    pub col: usize,
+    pub kind: MoveKind,
+}
+
+impl Play {
+    pub fn drop(col: usize) -> Self {
+        Play {
+            col,
+            kind: MoveKind::Drop,
+        }
+    }
+
+    pub fn pop(col: usize) -> Self {
+        Play {
+            col,
+            kind: MoveKind::Pop,
+        }
+    }
+
+    pub fn place(position: Position) -> Self {
+        Play {
+            col: position.col,
+            kind: MoveKind::Place(position.row),
+        }
+    }
+
+    pub fn power_up(power: PowerUp, col: usize) -> Self {
+        Play {
+            col,
+            kind: MoveKind::PowerUp(power),
+        }
+    }
+}
+
+impl fmt::Display for Play {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            MoveKind::Drop => write!(f, "{}", self.col),
+            MoveKind::Pop => write!(f, "p{}", self.col),
+            MoveKind::PowerUp(power) => write!(f, "{}{}", power.letter(), self.col),
+            MoveKind::Place(row) => write!(f, "{},{}", self.col, row),
+        }
+    }
+}
+
+impl std::str::FromStr for Play {
+    type Err = ProtocolError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || ProtocolError(format!("invalid move: {}", text));
+        if let Some((col, row)) = text.split_once(',') {
+            let col = col.trim().parse().map_err(|_| invalid())?;
+            let row = row.trim().parse().map_err(|_| invalid())?;
+            return Ok(Play::place(Position { row, col }));
+        }
+
+        let power = PowerUp::ALL
+            .into_iter()
+            .find(|power| text.starts_with(power.letter()));
+        let (kind, col) = match (text.strip_prefix('p'), power) {
+            (Some(col), _) => (MoveKind::Pop, col),
+            (None, Some(power)) => (MoveKind::PowerUp(power), &text[1..]),
+            (None, None) => (MoveKind::Drop, text),
+        };
+        match col.parse() {
+            Ok(col) => Ok(Play { col, kind }),
+            _ => Err(ProtocolError(format!("invalid move: {}", text))),
+        }
+    }
+}
+
+/// A move that was played. For a pop, `position` is the bottom cell the disc came from.
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Move {
-    pub player: Player,
+    pub player: PlayerId,
     pub position: Position,
+    pub kind: MoveKind,
 }
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct Board {
     board: Vec<Vec<Cell>>,
     game_state: GameState,
-    players: [Player; 2],
-    current_player: Player,
+    size: BoardSize,
+    /// In turn order.
+    players: Vec<Player>,
+    current_player: PlayerId,
+    /// Players knocked out of the game, who no longer take turns.
+    eliminated: Vec<PlayerId>,
+    /// Players who made a line in `Variant::Elimination`, in the order they did.
+    finished: Vec<PlayerId>,
+    last_move: Option<Move>,
+    variant: Variant,
+    /// Played by instead of the variant's rules, see `with_rules`.
+    rules: Option<CustomRules>,
+    /// Every line a four can be made in, see `windows`.
+    windows: Arc<Vec<Vec<Position>>>,
+    pop_ten: PopTenState,
+    /// The power-ups each player has left, by `PlayerId::index`.
+    power_ups: [Inventory; 4],
+    /// Every position so far with the player to move, kept for the repetition rule.
+    history: Vec<u64>,
 }
 
 impl fmt::Display for Cell {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Cell::Empty => write!(f, "The Cell is empty"),
-            Cell::Player(player) => write!(f, "{} has this Cell!", player),
+            Cell::Blocked => write!(f, "The Cell is blocked"),
+            Cell::Wall => write!(f, "The Cell has a wall"),
+            Cell::Player(id) => write!(f, "Player {} has this Cell!", id),
         }
     }
 }
@@ -59,18 +526,35 @@ impl fmt::Display for GameState {
         match self {
             GameState::InProgress => write!(f, "The Game is still in progress."),
             GameState::Draw => write!(f, "The game is Draw"),
-            GameState::Win(player) => write!(f, "Player {} has won!", player.id),
+            GameState::Win(id) => write!(f, "Player {} has won!", id),
         }
     }
 }
 
 impl fmt::Display for Move {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{} has made a Move! They have placed it in {}!",
-            self.player, self.position
-        )
+        match self.kind {
+            MoveKind::Drop => write!(
+                f,
+                "Player {} has made a Move! They have placed it in {}!",
+                self.player, self.position
+            ),
+            MoveKind::Pop => write!(
+                f,
+                "Player {} has made a Move! They have popped their disc out of {}!",
+                self.player, self.position
+            ),
+            MoveKind::PowerUp(power) => write!(
+                f,
+                "Player {} has made a Move! They have used their {} on {}!",
+                self.player, power, self.position
+            ),
+            MoveKind::Place(_) => write!(
+                f,
+                "Player {} has made a Move! They have placed it on {}!",
+                self.player, self.position
+            ),
+        }
     }
 }
 
@@ -78,28 +562,33 @@ impl fmt::Display for Board {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut seq = String::new();
 
-        let col_chr = [
-            '0', ' ', '1', ' ', '2', ' ', '3', ' ', '4', ' ', '5', ' ', '6', '\n', '\n',
-        ];
-
         seq.push('\n');
 
-        for row in 0..ROWS {
-            for col in 0..COLS {
+        for row in 0..self.size.rows {
+            for col in 0..self.size.cols {
                 let ch = match self.board[col][row] {
                     Cell::Empty => '_',
-                    Cell::Player(player) => match player.id {
-                        PlayerId::One => 'X',
-                        PlayerId::Two => 'O',
-                    },
+                    Cell::Blocked => layout::BLOCKED,
+                    Cell::Wall => '+',
+                    Cell::Player(id) => id.symbol(),
                 };
                 seq.push(ch);
                 seq.push(' ');
             }
+            // Without gravity the row has to be given too
+            if !self.variant.has_gravity() {
+                seq.push_str(&row.to_string());
+            }
             seq.push('\n');
         }
-        for c in col_chr {
-            seq.push(c);
+        // Only the last digit fits above wide boards
+        let labels: Vec<String> = (0..self.size.cols)
+            .map(|col| (col % 10).to_string())
+            .collect();
+        seq.push_str(&labels.join(" "));
+        seq.push_str("\n\n");
+        if let Some(scores) = self.scores() {
+            seq.push_str(&format!("Score: {}\n", format_scores(&scores)));
         }
         write!(f, "{}", seq)
     }
@@ -107,23 +596,242 @@ impl fmt::Display for Board {
 
 impl Board {
     pub fn new(player1_type: PlayerType, player2_type: PlayerType) -> Self {
-        let players = [
-            Player {
-                id: PlayerId::One,
-                kind: player1_type,
-            },
-            Player {
-                id: PlayerId::Two,
-                kind: player2_type,
-            },
-        ];
+        Board::new_multiplayer(&[player1_type, player2_type])
+    }
+
+    /**
+     * A board for two to four players, Player 1 first and so on. Pop Out and
+     * Pop Ten are for two players only.
+     */
+    pub fn new_multiplayer(kinds: &[PlayerType]) -> Self {
+        assert!(
+            (2..=PlayerId::ALL.len()).contains(&kinds.len()),
+            "a game needs 2 to 4 players"
+        );
+        let players: Vec<Player> = PlayerId::ALL
+            .iter()
+            .zip(kinds)
+            .map(|(&id, kind)| Player {
+                id,
+                kind: kind.clone(),
+            })
+            .collect();
+        let size = BoardSize::default();
 
         Board {
-            board: vec![vec![Cell::Empty; ROWS]; COLS], // This is Column Major instead of Row Major
+            board: vec![vec![Cell::Empty; size.rows]; size.cols], // This is Column Major instead of Row Major
             game_state: GameState::InProgress,
+            size,
+            current_player: players[0].id,
             players,
-            current_player: players[0],
+            eliminated: Vec::new(),
+            finished: Vec::new(),
+            last_move: None,
+            variant: Variant::Classic,
+            rules: None,
+            windows: Arc::new(all_windows(size, false)),
+            pop_ten: PopTenState::default(),
+            power_ups: [Inventory::default(); 4],
+            history: Vec::new(),
+        }
+    }
+
+    /// The same board, played by the rules of `variant`. Panics if it is for fewer players.
+    pub fn with_variant(mut self, variant: Variant) -> Self {
+        assert!(
+            self.players.len() == 2 || !variant.is_two_player(),
+            "{:?} is for two players",
+            variant
+        );
+        self.variant = variant;
+        let inventory = match variant {
+            Variant::PowerUps => Inventory::starting(),
+            _ => Inventory::default(),
+        };
+        self.power_ups = [inventory; 4];
+        self.reset_windows();
+        self
+    }
+
+    /**
+     * The same board, played by `rules` rather than by its variant's. The
+     * variant still decides how the board is drawn and what the lines are,
+     * so set it first when the rules build on one.
+     */
+    pub fn with_rules(mut self, rules: impl Ruleset + 'static) -> Self {
+        self.rules = Some(CustomRules(Arc::new(rules)));
+        self
+    }
+
+    /// The rules the board is played by.
+    pub fn rules(&self) -> &dyn Ruleset {
+        match &self.rules {
+            Some(CustomRules(rules)) => rules.as_ref(),
+            None => self.variant.rules(),
+        }
+    }
+
+    /// An empty board of `size`, with the same players and rules. Panics if the size is not valid.
+    pub fn with_size(mut self, size: BoardSize) -> Self {
+        assert!(size.is_valid(), "invalid board size: {}", size);
+        self.size = size;
+        self.board = vec![vec![Cell::Empty; size.rows]; size.cols];
+        self.reset_windows();
+        self
+    }
+
+    /**
+     * The board of `layout`, with its blocked cells and discs and the
+     * player it has to move, keeping the players, rules and how many in a
+     * row win. Fails if the layout is empty or too big, has cells off its
+     * own edges, has discs of players who are not playing or discs floating
+     * over empty cells where there is gravity, or already has a line.
+     */
+    pub fn with_layout(self, layout: &Layout) -> Result<Self, FormatError> {
+        let size = BoardSize {
+            rows: layout.rows,
+            cols: layout.cols,
+            ..self.size
+        };
+        let sides = 1..=BoardSize::MAX_SIDE;
+        if !sides.contains(&size.rows) || !sides.contains(&size.cols) {
+            return Err(FormatError::whole(format!(
+                "a {} by {} layout is empty or over {} a side",
+                size.cols,
+                size.rows,
+                BoardSize::MAX_SIDE
+            )));
+        }
+        if size.win_length > size.rows.max(size.cols) {
+            return Err(FormatError::whole(format!(
+                "a {} by {} layout has no room for {} in a row",
+                size.cols, size.rows, size.win_length
+            )));
+        }
+        let positions = layout.discs.iter().map(|&(position, _)| position);
+        if let Some(off) = positions
+            .chain(layout.blocked.iter().copied())
+            .find(|position| position.row >= size.rows || position.col >= size.cols)
+        {
+            return Err(FormatError::whole(format!(
+                "row {} column {} is off the {} by {} layout",
+                off.row, off.col, size.cols, size.rows
+            )));
+        }
+        let playing = |id: PlayerId| self.players.iter().any(|p| p.id == id);
+        if let Some(id) = layout.to_move.filter(|&id| !playing(id)) {
+            return Err(FormatError::whole(format!("Player {} is not playing", id)));
+        }
+
+        let mut board = self.with_size(size);
+        if let Some(id) = layout.to_move {
+            board.current_player = id;
+        }
+        for &position in &layout.blocked {
+            board.board[position.col][position.row] = Cell::Blocked;
+        }
+        for &(position, id) in &layout.discs {
+            if !board.players.iter().any(|p| p.id == id) {
+                return Err(FormatError::whole(format!("Player {} is not playing", id)));
+            }
+            board.board[position.col][position.row] = Cell::Player(id);
+        }
+
+        let gravity = board.variant.has_gravity();
+        for &(Position { row, col }, _) in &layout.discs {
+            if gravity && row + 1 < size.rows && board.board[col][row + 1] == Cell::Empty {
+                return Err(FormatError::whole(format!(
+                    "disc floating in column {}",
+                    col
+                )));
+            }
+        }
+        board.reset_windows();
+        if board.players.iter().any(|p| board.has_four(p.id)) {
+            return Err(FormatError::whole("the layout already has a line"));
         }
+        Ok(board)
+    }
+
+    /// Whether any cells are blocked, see `with_layout`.
+    pub fn has_blocked_cells(&self) -> bool {
+        self.board
+            .iter()
+            .flatten()
+            .any(|&cell| cell == Cell::Blocked)
+    }
+
+    /**
+     * Whether this is plain Connect 4, the only game the engine protocol and
+     * opening books know: two players dropping discs on the standard board.
+     */
+    pub fn is_classic(&self) -> bool {
+        self.variant == Variant::Classic
+            && self.rules.is_none()
+            && self.size == BoardSize::default()
+            && self.players.len() == 2
+            && !self.has_blocked_cells()
+    }
+
+    /// Lines through blocked cells can never be made, so they are left out.
+    fn reset_windows(&mut self) {
+        let windows = all_windows(self.size, self.variant.wraps())
+            .into_iter()
+            .filter(|window| {
+                window
+                    .iter()
+                    .all(|pos| self.board[pos.col][pos.row] != Cell::Blocked)
+            })
+            .collect();
+        self.windows = Arc::new(windows);
+    }
+
+    /**
+     * Players take turns in the order of `order`, the first of them to move.
+     * Fails unless it names each player once.
+     */
+    pub fn with_turn_order(mut self, order: &[PlayerId]) -> Result<Self, ProtocolError> {
+        if order.len() != self.players.len() || !self.players.iter().all(|p| order.contains(&p.id))
+        {
+            return Err(ProtocolError(format!(
+                "the turn order must name each of the {} players once",
+                self.players.len()
+            )));
+        }
+        self.players = order.iter().map(|&id| self.player(id).clone()).collect();
+        self.current_player = order[0];
+        Ok(self)
+    }
+
+    pub fn get_variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn get_size(&self) -> BoardSize {
+        self.size
+    }
+
+    /// The players in turn order.
+    pub fn get_players(&self) -> &[Player] {
+        &self.players
+    }
+
+    pub fn get_last_move(&self) -> Option<Move> {
+        self.last_move
+    }
+
+    /// The power-ups `id` has left, always none outside `Variant::PowerUps`.
+    pub fn get_inventory(&self, id: PlayerId) -> Inventory {
+        self.power_ups[id.index()]
+    }
+
+    pub(crate) fn inventory_mut(&mut self, id: PlayerId) -> &mut Inventory {
+        &mut self.power_ups[id.index()]
+    }
+
+    /// Each player's points, the discs captured in Pop Ten, or `None` without a score.
+    pub fn scores(&self) -> Option<Vec<usize>> {
+        self.rules().scores(self)
     }
 
     pub fn new_ai_vs_human(diff: usize) -> Self {
@@ -149,47 +857,49 @@ impl Board {
     }
 
     pub fn play_game(&mut self) {
-        let start_game_msg = |current_player: Player, players: [Player; 2]| {
-            println!("Welcome to Connect 4");
-            println!("There are 2 players playing");
+        self.play_game_with_spectators(&Broadcaster::new());
+    }
+
+    /// Plays the game like `play_game`, publishing every move to `spectators`.
+    pub fn play_game_with_spectators(&mut self, spectators: &Broadcaster) {
+        let record = self.play_game_with_config(GameConfig::random(), spectators);
+        println!("To replay this game use seed {}", record.seed);
+    }
 
-            for player in players {
+    /// Plays the game with every random choice drawn from `config.seed`.
+    pub fn play_game_with_config(
+        &mut self,
+        config: GameConfig,
+        spectators: &Broadcaster,
+    ) -> GameRecord {
+        let mut rng = config.rng();
+
+        let start_game_msg = |first_player: PlayerId, board: &Board| {
+            println!("Welcome to Connect {}", board.size.win_length);
+            println!("There are {} players playing", board.players.len());
+
+            for player in &board.players {
                 println!("{}", player);
             }
 
             println!(
                 "Player {} is going First, They are {}",
-                match current_player.id {
-                    PlayerId::One => 1,
-                    PlayerId::Two => 2,
-                },
-                match current_player.id {
-                    PlayerId::One => "X",
-                    PlayerId::Two => "O",
-                }
+                first_player,
+                first_player.symbol()
             );
         };
 
-        let start_turn_msg = |current_player: Player| {
+        let start_turn_msg = |current_player: PlayerId| {
             println!(
                 "Player {}'s Turn, They are {}",
-                match current_player.id {
-                    PlayerId::One => 1,
-                    PlayerId::Two => 2,
-                },
-                match current_player.id {
-                    PlayerId::One => "X",
-                    PlayerId::Two => "O",
-                }
+                current_player,
+                current_player.symbol()
             );
         };
 
         let end_turn_msg = |player_move: Option<Move>, board: &mut Board| {
-            match player_move {
-                Some(player_move) => {
-                    println!("{}", player_move);
-                }
-                None => {}
+            if let Some(player_move) = player_move {
+                println!("{}", player_move);
             };
 
             println!("{}", board.clone());
@@ -205,185 +915,480 @@ impl Board {
             println!("The final Board is...\n\n{}", board.clone());
         };
 
-        let index = (rand::random::<f32>() * self.players.len() as f32).floor() as usize; // chooses which player goes first
-        self.current_player = self.players[index];
+        let first_player = config
+            .first_player
+            .resolve(config.game_number, self, &mut rng);
+        self.current_player = first_player;
+        let mut moves = Vec::new();
 
-        start_game_msg(self.current_player.clone(), self.players.clone());
+        start_game_msg(self.current_player, self);
+        spectators.start(self);
 
-        while self.game_state == GameState::InProgress {
-            let col: usize;
-            start_turn_msg(self.current_player.clone());
+        // Started on a human's turn when the other player is an AI that ponders
+        let mut pondering: Option<Ponder> = None;
+        let mut last_play = None;
 
-            if let PlayerType::AI(ai) = &self.current_player.kind {
-                let best_move = ai.best_move(&self);
+        while self.game_state == GameState::InProgress {
+            let play: Play;
+            start_turn_msg(self.current_player);
+
+            let kind = self.player(self.current_player).kind.clone();
+            if let PlayerType::AI(ai) = kind {
+                let pondered = pondering
+                    .take()
+                    .zip(last_play)
+                    .and_then(|(ponder, reply)| ponder.finish(reply))
+                    .and_then(|info| info.best_move)
+                    // Only a plain search gives the same move, and draws nothing from rng
+                    .filter(|_| {
+                        !ai.in_book(self) && ai.temperature <= 0.0 && ai.blunder_chance <= 0.0
+                    });
+                let best_move = pondered.or_else(|| ai.choose_play(self, &mut rng));
                 match best_move {
-                    Some(m) => col = m,
+                    Some(m) => play = m,
                     None => {
                         println!("AI Could not find a good move");
-                        col = 8 // Invalid number
+                        play = Play::drop(self.size.cols) // Invalid number
+                    }
+                }
+            } else if let PlayerType::Agent(mut agent) = kind {
+                let result = agent.choose_move(self, &moves).and_then(|chosen| {
+                    match self.valid_play(chosen) {
+                        true => Ok(chosen),
+                        false => Err(AgentError::IllegalMove(chosen)),
+                    }
+                });
+                match result {
+                    Ok(chosen) => play = chosen,
+                    Err(err) => {
+                        println!("{} {}, and forfeits the game", agent.name(), err);
+                        self.forfeit();
+                        end_turn_msg(None, self);
+                        continue;
                     }
                 }
             } else {
+                // Pondering guesses at one reply, so only with a single opponent
+                let opponent = self.players.iter().find(|p| p.id != self.current_player);
+                let opponent = opponent.filter(|_| self.players.len() == 2);
+                if let Some(PlayerType::AI(ai)) = opponent.map(|p| &p.kind) {
+                    if ai.ponder && pondering.is_none() {
+                        pondering = Some(Ponder::start(ai.clone(), self));
+                    }
+                }
+
                 let mut input = String::new();
-                println!("Enter your move (Enter a number from 0-6):");
+                println!("{}", self.move_prompt());
                 io::stdin()
                     .read_line(&mut input)
                     .expect("Failed to read input");
 
-                col = input.trim().parse().expect("Please enter a valid number");
+                play = input.trim().parse().expect("Please enter a valid move");
 
-                if !self.valid_move(col) {
+                if !self.valid_play(play) {
                     continue;
                 }
             }
 
-            let player_move: Option<Move> = self.play_move(col);
+            let player_move: Option<Move> = self.play(play);
+            last_play = Some(play);
+
+            if let Some(player_move) = player_move {
+                moves.push(play);
+                let evaluation = self.evaluate_for(PlayerId::One);
+                spectators.publish_move(player_move, self, evaluation);
+            }
+
+            end_turn_msg(player_move, self);
+        }
+
+        spectators.finish(self.game_state);
+        end_game_msg(self);
+
+        GameRecord {
+            seed: config.seed,
+            first_player,
+            moves,
+            state: self.game_state,
+            scores: self.scores(),
+        }
+    }
+
+    fn move_prompt(&self) -> String {
+        let last = self.size.cols - 1;
+        match self.variant {
+            Variant::PopOut => format!(
+                "Enter your move (0-{} to drop, or p0-p{} to pop a disc out):",
+                last, last
+            ),
+            Variant::PopTen if self.pop_ten.holding => format!(
+                "Put your disc back on a column (Enter a number from 0-{}):",
+                last
+            ),
+            Variant::PopTen if self.pop_ten.filled => {
+                format!("Pop one of your discs out (p0-p{}):", last)
+            }
+            Variant::PopTen => format!("Fill the board (Enter a number from 0-{}):", last),
+            Variant::PowerUps => format!(
+                "Enter your move (0-{} to drop, or a, b, w or t and a column for an anvil, bomb, \
+                 wall or play twice. You have: {}):",
+                last,
+                self.get_inventory(self.current_player)
+            ),
+            Variant::FreePlacement => format!(
+                "Enter your move (a column from 0-{} and a row from 0-{}, e.g. 3,2):",
+                last,
+                self.size.rows - 1
+            ),
+            _ => format!("Enter your move (Enter a number from 0-{}):", last),
+        }
+    }
+
+    pub fn play_move(&mut self, col: usize) -> Option<Move> {
+        self.play(Play::drop(col))
+    }
+
+    /// Puts a disc on `position`, for boards without gravity. Returns `None` if the cell is not free.
+    pub fn place(&mut self, position: Position) -> Option<Move> {
+        self.play(Play::place(position))
+    }
+
+    /// Plays a drop or, where the rules allow, a pop. Returns `None` if the play is not allowed.
+    pub fn play(&mut self, play: Play) -> Option<Move> {
+        // Custom rules are shared so the board can be changed while they play
+        match self.rules.clone() {
+            Some(CustomRules(rules)) => rules.apply(self, play),
+            None => self.variant.rules().apply(self, play),
+        }
+    }
+
+    pub fn valid_play(&self, play: Play) -> bool {
+        self.rules().is_legal(self, play)
+    }
+
+    /// Every play allowed for the player to move, drops first.
+    pub fn legal_plays(&self) -> Vec<Play> {
+        self.rules().legal_plays(self)
+    }
+
+    pub fn valid_move(&mut self, col: usize) -> bool {
+        self.valid_play(Play::drop(col))
+    }
+
+    /// The next player in turn order who is still in the game.
+    fn change_current_player(&mut self) {
+        let current = self
+            .players
+            .iter()
+            .position(|p| p.id == self.current_player)
+            .unwrap();
+        let count = self.players.len();
+        if let Some(next) = (1..=count)
+            .map(|step| self.players[(current + step) % count].id)
+            .find(|id| !self.eliminated.contains(id))
+        {
+            self.current_player = next;
+        }
+    }
+
+    /**
+     * Puts a disc of the player to move on top of `col`, without passing the
+     * turn or checking for the end of the game. Returns `None` if the column
+     * is full or off the board. For building a `Ruleset`, use `play` to make
+     * moves.
+     */
+    pub(crate) fn drop_disc(&mut self, col: usize) -> Option<Position> {
+        let position = self.drop_cell(col, Cell::Player(self.current_player))?;
+        self.record_move(position, MoveKind::Drop);
+        Some(position)
+    }
+
+    /// `drop_disc` with a wall instead of the player's disc, see `PowerUp::Wall`.
+    pub(crate) fn drop_wall(&mut self, col: usize) -> Option<Position> {
+        let position = self.drop_cell(col, Cell::Wall)?;
+        self.record_move(position, MoveKind::PowerUp(PowerUp::Wall));
+        Some(position)
+    }
+
+    /// `drop_disc`, but everything in the column is crushed first, see `PowerUp::Anvil`.
+    pub(crate) fn drop_anvil(&mut self, col: usize) -> Option<Position> {
+        // Down to the bottom or the first blocked cell
+        let column = self.board.get_mut(col)?;
+        let crushed = column
+            .iter()
+            .take_while(|&&cell| cell != Cell::Blocked)
+            .count();
+        column[..crushed].fill(Cell::Empty);
+        let position = self.drop_cell(col, Cell::Player(self.current_player))?;
+        self.record_move(position, MoveKind::PowerUp(PowerUp::Anvil));
+        Some(position)
+    }
+
+    /// Puts a disc of the player to move straight on `position`, which must be empty.
+    pub(crate) fn place_disc(&mut self, position: Position) -> Option<Position> {
+        let cell = self.board.get_mut(position.col)?.get_mut(position.row)?;
+        if *cell != Cell::Empty {
+            return None;
+        }
+        *cell = Cell::Player(self.current_player);
+        self.record_move(position, MoveKind::Place(position.row));
+        Some(position)
+    }
+
+    fn drop_cell(&mut self, col: usize, cell: Cell) -> Option<Position> {
+        // The disc falls until it lands on a disc, a blocked cell or the bottom
+        let column = self.board.get(col)?;
+        let row = column
+            .iter()
+            .take_while(|&&cell| cell == Cell::Empty)
+            .count()
+            .checked_sub(1)?;
+        self.board[col][row] = cell;
+        Some(Position { row, col })
+    }
+
+    /// Takes the disc out of the bottom of `col` and lets the rest fall, returning where it was.
+    pub(crate) fn pop_disc(&mut self, col: usize) -> Option<Position> {
+        if !matches!(self.board.get(col)?.last(), Some(Cell::Player(_))) {
+            return None;
+        }
+        let position = self.remove_bottom(col)?;
+        self.record_move(position, MoveKind::Pop);
+        Some(position)
+    }
+
+    /// `pop_disc` for any disc or wall, whoever it belongs to, see `PowerUp::Bomb`.
+    pub(crate) fn bomb(&mut self, col: usize) -> Option<Position> {
+        let position = self.remove_bottom(col)?;
+        self.record_move(position, MoveKind::PowerUp(PowerUp::Bomb));
+        Some(position)
+    }
+
+    fn remove_bottom(&mut self, col: usize) -> Option<Position> {
+        let bottom = self.size.rows - 1;
+        if matches!(self.board.get(col)?[bottom], Cell::Empty | Cell::Blocked) {
+            return None;
+        }
+        // Everything above falls one row, up to the first blocked cell
+        let top = (0..bottom)
+            .rev()
+            .find(|&row| self.board[col][row] == Cell::Blocked)
+            .map_or(0, |row| row + 1);
+        self.board[col][top..].rotate_right(1);
+        self.board[col][top] = Cell::Empty;
+        Some(Position { row: bottom, col })
+    }
+
+    fn record_move(&mut self, position: Position, kind: MoveKind) {
+        self.last_move = Some(Move {
+            player: self.current_player,
+            position,
+            kind,
+        });
+    }
 
-            end_turn_msg(player_move.clone(), self);
-        }
+    pub(crate) fn pass_turn(&mut self) {
+        self.change_current_player();
+    }
 
-        end_game_msg(self);
+    pub(crate) fn set_game_state(&mut self, state: GameState) {
+        self.game_state = state;
     }
 
-    pub fn play_move(&mut self, col: usize) -> Option<Move> {
-        if !self.valid_move(col) {
-            return None;
+    /// Takes `id` out of the game: they keep their discs but no longer take turns.
+    pub(crate) fn eliminate(&mut self, id: PlayerId) {
+        if !self.eliminated.contains(&id) {
+            self.eliminated.push(id);
         }
+        if self.current_player == id {
+            self.change_current_player();
+        }
+    }
 
-        for row in (0..ROWS).rev() {
-            if self.board[col][row] == Cell::Empty {
-                let player_move = Some(Move {
-                    player: self.current_player,
-                    position: Position { row, col },
-                });
-
-                self.board[col][row] = Cell::Player(self.current_player);
-                self.change_current_player();
-                self.check_win();
-                return player_move;
-            }
+    /// Takes `id` out of the turns for having made a line, see `Variant::Elimination`.
+    pub(crate) fn finish(&mut self, id: PlayerId) {
+        if !self.finished.contains(&id) {
+            self.finished.push(id);
         }
+        self.eliminate(id);
+    }
 
-        return None;
+    /// The players who have made a line in `Variant::Elimination`, first place first.
+    pub fn finished(&self) -> &[PlayerId] {
+        &self.finished
     }
 
-    pub fn valid_move(&mut self, col: usize) -> bool {
-        if col >= COLS {
-            return false;
+    /// The players still in the game, in turn order.
+    pub fn active_players(&self) -> Vec<PlayerId> {
+        self.players
+            .iter()
+            .map(|p| p.id)
+            .filter(|id| !self.eliminated.contains(id))
+            .collect()
+    }
+
+    /**
+     * The player to move gives up, e.g. an `Agent` that crashed. They are
+     * out of the game, and if only one player is left that player wins,
+     * unless someone has already finished first.
+     */
+    pub(crate) fn forfeit(&mut self) {
+        self.eliminate(self.current_player);
+        if let [last] = self.active_players()[..] {
+            let winner = self.finished.first().copied().unwrap_or(last);
+            self.game_state = GameState::Win(winner);
         }
+    }
 
-        for row in (0..ROWS).rev() {
-            if self.board[col][row] == Cell::Empty {
-                return true;
-            }
+    /**
+     * Whether no more discs fit. With gravity that is when no column can take
+     * another, although cells under a blocked cell may still be empty.
+     */
+    pub fn is_full(&self) -> bool {
+        if !self.variant.has_gravity() {
+            return self.board.iter().flatten().all(|&cell| cell != Cell::Empty);
         }
+        self.board.iter().all(|col| col[0] != Cell::Empty)
+    }
 
-        return false;
+    /**
+     * Empty cells a disc can still get to. With gravity those are the ones
+     * above the top disc or blocked cell of each column.
+     */
+    pub fn cells_left(&self) -> usize {
+        let gravity = self.variant.has_gravity();
+        self.board
+            .iter()
+            .map(|col| match gravity {
+                true => col.iter().take_while(|&&cell| cell == Cell::Empty).count(),
+                false => col.iter().filter(|&&cell| cell == Cell::Empty).count(),
+            })
+            .sum()
     }
 
-    fn change_current_player(&mut self) {
-        if self.current_player == self.players[0] {
-            self.current_player = self.players[1];
-        } else {
-            self.current_player = self.players[0];
-        }
-    }
-
-    fn check_win(&mut self) {
-        // Check horizontal, vertical, and diagonal wins
-        for row in (0..ROWS).rev() {
-            for col in 0..COLS {
-                if let Cell::Player(player) = self.board[col][row] {
-                    // Check Horizontal
-                    if col + WINNING_LENGTH <= COLS
-                        && (0..WINNING_LENGTH)
-                            .all(|i| self.board[col + i][row] == Cell::Player(player))
-                    {
-                        self.game_state = GameState::Win(player);
-                        return;
-                    }
+    /// Records the position and says whether it has now come up three times.
+    pub(crate) fn repeats_third_time(&mut self) -> bool {
+        let key = self.repetition_key();
+        self.history.push(key);
+        self.history.iter().filter(|&&seen| seen == key).count() >= 3
+    }
 
-                    // Check Vertical
-                    if row + WINNING_LENGTH <= ROWS
-                        && (0..WINNING_LENGTH)
-                            .all(|i| self.board[col][row + i] == Cell::Player(player))
-                    {
-                        self.game_state = GameState::Win(player);
-                        return;
-                    }
+    pub(crate) fn pop_ten(&self) -> &PopTenState {
+        &self.pop_ten
+    }
 
-                    // Check diagonal (bottom-left to top-right)
-                    if col + WINNING_LENGTH <= COLS
-                        && row + WINNING_LENGTH <= ROWS
-                        && (0..WINNING_LENGTH)
-                            .all(|i| self.board[col + i][row + i] == Cell::Player(player))
-                    {
-                        self.game_state = GameState::Win(player);
-                        return;
-                    }
+    pub(crate) fn pop_ten_mut(&mut self) -> &mut PopTenState {
+        &mut self.pop_ten
+    }
 
-                    // Check diagonal (top-left to bottom-right)
-                    if col + WINNING_LENGTH <= COLS
-                        && row >= WINNING_LENGTH - 1
-                        && (0..WINNING_LENGTH)
-                            .all(|i| self.board[col + i][row - i] == Cell::Player(player))
-                    {
-                        self.game_state = GameState::Win(player);
-                        return;
-                    }
-                }
-            }
-        }
+    /// Panics if `id` is not playing.
+    pub fn player(&self, id: PlayerId) -> &Player {
+        self.players
+            .iter()
+            .find(|p| p.id == id)
+            .unwrap_or_else(|| panic!("Player {} is not in this game", id))
+    }
+
+    /**
+     * Every line of `win_length` cells a four can be made in: horizontal,
+     * vertical, or diagonal, and on a cylinder also those that wrap around.
+     */
+    pub fn windows(&self) -> &[Vec<Position>] {
+        &self.windows
+    }
+
+    fn owns(&self, position: Position, id: PlayerId) -> bool {
+        matches!(self.board[position.col][position.row], Cell::Player(owner) if owner == id)
+    }
 
-        // Check for draw
-        if self
-            .board
+    /// Whether `id` has four in a row anywhere: horizontal, vertical, or diagonal.
+    pub fn has_four(&self, id: PlayerId) -> bool {
+        self.windows()
             .iter()
-            .all(|col| col.iter().all(|cell| *cell != Cell::Empty))
-        {
-            self.game_state = GameState::Draw;
-        }
+            .any(|window| window.iter().all(|&position| self.owns(position, id)))
+    }
+
+    /// Whether the disc at `position` belongs to `id` and is part of a four.
+    pub fn in_four(&self, position: Position, id: PlayerId) -> bool {
+        self.windows().iter().any(|window| {
+            window.contains(&position) && window.iter().all(|&cell| self.owns(cell, id))
+        })
     }
 
     /**
-     * The window will be a 4 by 4 window that will check the Horizontal, Vertical, and diagonally.
+     * `position_key` plus whose turn it is, which the discs alone do not tell
+     * once discs can be popped, and in Pop Ten the captures and a held disc.
      */
-    fn score_window(window: &[Cell], player: Player) -> i32 {
-        let opponent_id = match player.id {
-            PlayerId::One => PlayerId::Two,
-            PlayerId::Two => PlayerId::One,
-        };
+    fn repetition_key(&self) -> u64 {
+        let turn = (self.current_player.index() as u64) << 62;
+        let [x, o] = self.pop_ten.captured;
+        // position_key uses the low COLS * (ROWS + 1) = 49 bits
+        let pop_ten = (x as u64) << 49 | (o as u64) << 54 | (self.pop_ten.holding as u64) << 59;
+        self.position_key() | pop_ten | turn
+    }
 
-        let opponent = Player {
-            id: opponent_id,
-            kind: player.kind,
+    /**
+     * One line of `win_length` cells, horizontal, vertical or diagonal, scored
+     * by how many of them `id` or a single opponent has filled, with the
+     * `EvalWeights` for twos, threes and fours.
+     */
+    fn score_window(
+        &self,
+        window: &[Position],
+        id: PlayerId,
+        odd_player: Option<PlayerId>,
+        weights: &EvalWeights,
+    ) -> i32 {
+        let owner = |cell: Cell| match cell {
+            Cell::Player(id) => Some(id),
+            Cell::Empty | Cell::Blocked | Cell::Wall => None,
         };
 
-        let mut score = 0;
-        let two_in_a_row: i32 = 500;
-        let three_in_a_row: i32 = 5000;
-        let four_in_a_row: i32 = 100000;
-        let block_score: i32 = 4000;
+        let cells = window.iter().map(|pos| self.board[pos.col][pos.row]);
+        // No one can make a line through a wall
+        if cells.clone().any(|cell| cell == Cell::Wall) {
+            return 0;
+        }
 
-        let count_player = window
-            .iter()
-            .filter(|&&c| c == Cell::Player(player))
-            .count();
-        let count_opponent = window
-            .iter()
-            .filter(|&&c| c == Cell::Player(opponent))
-            .count();
-        let count_empty = window.iter().filter(|&&c| c == Cell::Empty).count();
+        let count_player = cells.clone().filter(|&c| owner(c) == Some(id)).count();
+        let mut opponents = cells.clone().filter_map(owner).filter(|&other| other != id);
+        let count_opponent = match opponents.next() {
+            // With more than two players, a line shared by two opponents is no threat
+            Some(first) if opponents.all(|other| other == first) => {
+                cells.clone().filter(|&c| owner(c) == Some(first)).count()
+            }
+            Some(_) => return 0,
+            None => 0,
+        };
 
-        match (count_player, count_opponent, count_empty) {
-            (4, 0, 0) => score += four_in_a_row,
-            (3, 0, 1) => score += three_in_a_row,
-            (2, 0, 2) => score += two_in_a_row,
-            (0, 3, 1) => score -= block_score, // block opponent threat
-            _ => {}
+        // Counted back from a full line, so that longer lines score like fours
+        let n = window.len();
+        match (count_player, count_opponent) {
+            (0, 0) => 0,
+            (p, 0) if p == n => weights.four,
+            (p, 0) if p + 1 == n => {
+                // Which row the threat is on matters, see EvalWeights::odd_threat
+                let empty = window
+                    .iter()
+                    .find(|pos| self.board[pos.col][pos.row] == Cell::Empty)
+                    .unwrap();
+                let threat = Threat {
+                    position: *empty,
+                    player: id,
+                };
+                let parity_bonus = match (odd_player, threat.is_odd(self)) {
+                    (Some(odd), true) if odd == id => weights.odd_threat,
+                    (Some(odd), false) if odd != id => weights.even_threat,
+                    _ => 0,
+                };
+                weights.three + parity_bonus
+            }
+            (p, 0) if p + 2 == n => weights.two,
+            (0, o) if o == n => -weights.opponent_four,
+            (0, o) if o + 1 == n => -weights.opponent_three, // block opponent threat
+            (0, o) if o + 2 == n => -weights.opponent_two,
+            _ => 0,
         }
-
-        return score;
     }
 
     /**
@@ -391,93 +1396,198 @@ impl Board {
      * This is used to assign a scoring to a move done by the AI but can
      * also be used to show the game state to the players like in chess.
      *
-     * Pieces in the center = 3 points for each
-     * 2 in a row in any direction + 2 empty = 500 points for each
-     * 3 in a row in any direction + 1 empty = 5000 points for each
-     * 4 in a row = 100000 points
+     * Discs in the center columns and lines of two, three and four in any
+     * direction score as set in `EvalWeights::default()`, and the opponent's
+     * lines count against them. See `evaluate_with` for other weights.
+     *
+     * This only looks at the position from one side, see `evaluate_for` for
+     * a score that is the same for both players with the sign flipped.
      */
-    pub fn evaluate(self, player: Player) -> i32 {
-        let mut score: i32 = 0;
-        let center_score: i32 = 3;
+    pub fn evaluate(&self, player: &Player) -> i32 {
+        self.evaluate_with(player, &EvalWeights::default())
+    }
 
-        // Center Points
-        let center_col = COLS / 2;
-        let center_count = self.board[center_col]
-            .iter()
-            .filter(|&&cell| cell == Cell::Player(player))
-            .count();
+    /// Like `evaluate`, but with the given weights instead of the defaults.
+    pub fn evaluate_with(&self, player: &Player, weights: &EvalWeights) -> i32 {
+        let mut score = self.side_score(player.id, weights);
+        if weights.mode == EvalMode::Threats {
+            score += threats::threat_score(self, player.id, weights);
+        }
+        score
+    }
 
-        score += (center_count as i32) * center_score;
+    /**
+     * How much better the position is for `id` than for their opponent:
+     * `evaluate_for(One) == -evaluate_for(Two)`, as negamax needs. Only
+     * whose discs are whose matters, not whether a human or an AI plays them.
+     */
+    pub fn evaluate_for(&self, id: PlayerId) -> i32 {
+        self.evaluate_for_with(id, &EvalWeights::default())
+    }
 
-        // Evaluate all 4-cell windows
-        for row in 0..ROWS {
-            for col in 0..COLS {
-                // Horizontal
-                if col + 3 < COLS {
-                    let window = (0..4).map(|i| self.board[col + i][row]).collect::<Vec<_>>();
-                    score += Self::score_window(&window, player);
-                }
+    /// Like `evaluate_for`, but with the given weights instead of the defaults.
+    pub fn evaluate_for_with(&self, id: PlayerId, weights: &EvalWeights) -> i32 {
+        self.rules().evaluate(self, id, weights)
+    }
 
-                // Vertical
-                if row + 3 < ROWS {
-                    let window = (0..4).map(|i| self.board[col][row + i]).collect::<Vec<_>>();
-                    score += Self::score_window(&window, player);
-                }
+    /// The part of `evaluate_for_with` that only looks at the discs, whatever the rules.
+    pub fn shape_score(&self, id: PlayerId, weights: &EvalWeights) -> i32 {
+        // Careless players count the others' threes neither as a danger nor as their gain
+        let (ours, theirs) = match weights.mode {
+            EvalMode::Careless => (
+                EvalWeights {
+                    opponent_three: 0,
+                    ..*weights
+                },
+                EvalWeights {
+                    three: 0,
+                    odd_threat: 0,
+                    even_threat: 0,
+                    ..*weights
+                },
+            ),
+            _ => (*weights, *weights),
+        };
+        let others: i32 = self
+            .players
+            .iter()
+            .filter(|p| p.id != id)
+            .map(|p| self.side_score(p.id, &theirs))
+            .sum();
+        let mut score = self.side_score(id, &ours) - others;
+        if weights.mode == EvalMode::Threats {
+            // Already counts the opponent's threats against us
+            score += threats::threat_score(self, id, weights);
+        }
+        score
+    }
 
-                // Diagonal /
-                if col + 3 < COLS && row >= 3 {
-                    let window = (0..4)
-                        .map(|i| self.board[col + i][row - i])
-                        .collect::<Vec<_>>();
-                    score += Self::score_window(&window, player);
-                }
+    /// `evaluate_for` the player whose turn it is.
+    pub fn evaluate_side_to_move(&self) -> i32 {
+        self.evaluate_for(self.current_player)
+    }
 
-                // Diagonal \
-                if col + 3 < COLS && row + 3 < ROWS {
-                    let window = (0..4)
-                        .map(|i| self.board[col + i][row + i])
-                        .collect::<Vec<_>>();
-                    score += Self::score_window(&window, player);
-                }
-            }
+    /// Center columns and windows as seen by `id`.
+    fn side_score(&self, id: PlayerId, weights: &EvalWeights) -> i32 {
+        let mut score: i32 = 0;
+
+        // Center Points, though on a cylinder there is no center
+        let cols = self.size.cols;
+        for col in (0..cols).filter(|_| !self.variant.wraps()) {
+            let count = self.board[col]
+                .iter()
+                .filter(|&&cell| cell == Cell::Player(id))
+                .count();
+            // On other widths, the weight of the same place on a standard board
+            let standard = col * (COLS - 1) / (cols - 1).max(1);
+            score += (count as i32) * weights.center[standard];
+        }
+
+        // Evaluate all 4-cell windows
+        let odd_player = threats::odd_player(self);
+        for window in self.windows() {
+            score += self.score_window(window, id, odd_player, weights);
         }
 
-        return score;
+        score
     }
 
     pub fn get_game_state(&self) -> &GameState {
-        return &self.game_state;
+        &self.game_state
     }
 
     pub fn get_board(&self) -> &Vec<Vec<Cell>> {
-        return &self.board;
+        &self.board
     }
 
     pub fn get_current_player(&self) -> &Player {
-        return &self.current_player;
+        self.player(self.current_player)
+    }
+
+    /// Number of discs played so far.
+    pub fn moves_played(&self) -> usize {
+        self.board
+            .iter()
+            .map(|col| {
+                col.iter()
+                    .filter(|cell| matches!(cell, Cell::Player(_)))
+                    .count()
+            })
+            .sum()
+    }
+
+    /**
+     * A number that identifies the position, whoever the players are. Each
+     * column takes ROWS + 1 bits: one per disc from the bottom up, set for
+     * Player 1, then a 1 just above the top disc to mark the height. That
+     * only fits two players on boards up to the standard size, and no
+     * walls; other boards get a hash of their cells instead, which is just as good as a key but
+     * can collide. Blocked cells are left out, as they stay put all game.
+     */
+    pub fn position_key(&self) -> u64 {
+        // Without gravity discs can also float, which the heights cannot show
+        let walls = self.variant == Variant::PowerUps;
+        if walls
+            || !self.variant.has_gravity()
+            || self.players.len() > 2
+            || self.size.cols * (self.size.rows + 1) > COLS * (ROWS + 1)
+        {
+            let mut hasher = DefaultHasher::new();
+            for cell in self.board.iter().flatten() {
+                match cell {
+                    Cell::Empty | Cell::Blocked => None,
+                    Cell::Wall => Some(None),
+                    Cell::Player(id) => Some(Some(id)),
+                }
+                .hash(&mut hasher);
+            }
+            // Keep clear of the bits repetition_key adds
+            return hasher.finish() & ((1 << 49) - 1);
+        }
+
+        let rows = self.size.rows;
+        let mut key: u64 = 0;
+        for col in 0..self.size.cols {
+            let mut height = 0;
+            for row in (0..rows).rev() {
+                if let Cell::Player(id) = self.board[col][row] {
+                    if id == PlayerId::One {
+                        key |= 1 << (col * (rows + 1) + height);
+                    }
+                    height += 1;
+                }
+            }
+            key |= 1 << (col * (rows + 1) + height);
+        }
+        key
     }
 }
 
 #[cfg(test)]
 impl Board {
-    fn test_drop_col(&mut self, col: usize, row: usize, player: Player) -> Option<Move> {
+    fn test_drop_col(&mut self, col: usize, row: usize, player: PlayerId) -> Option<Move> {
         if self.board[col][row] == Cell::Empty {
             let player_move = Some(Move {
-                player: player,
+                player,
                 position: Position { row, col },
+                kind: MoveKind::Drop,
             });
 
             self.board[col][row] = Cell::Player(player);
-            self.check_win();
+            match self.rules.clone() {
+                Some(CustomRules(rules)) => rules.check_end(self),
+                None => self.variant.rules().check_end(self),
+            }
             return player_move;
         }
-        return None;
+        None
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*; // Brings Board, Player, etc. into scope
+    use crate::difficulty::Difficulty;
 
     /**
      * Helper Function
@@ -489,7 +1599,7 @@ mod tests {
     }
 
     fn play_move_draw(board: &mut Board, moves: Vec<Vec<usize>>) {
-        let player = *board.get_current_player();
+        let player = board.get_current_player().id;
         for (row, cols) in moves.iter().enumerate() {
             for &col in cols {
                 board.test_drop_col(col, row, player);
@@ -545,7 +1655,7 @@ mod tests {
          */
 
         play_moves(&mut board, &[0, 1, 0, 1, 0, 1, 0]);
-        assert_eq!(board.get_game_state(), &GameState::Win(board.players[0]))
+        assert_eq!(board.get_game_state(), &GameState::Win(board.players[0].id))
     }
 
     #[test]
@@ -564,7 +1674,7 @@ mod tests {
          */
 
         play_moves(&mut board, &[0, 0, 1, 1, 2, 2, 3]);
-        assert_eq!(board.get_game_state(), &GameState::Win(board.players[0]))
+        assert_eq!(board.get_game_state(), &GameState::Win(board.players[0].id))
     }
 
     #[test]
@@ -583,7 +1693,7 @@ mod tests {
          */
 
         play_moves(&mut board, &[0, 1, 1, 2, 2, 3, 2, 3, 3, 2, 3]);
-        assert_eq!(board.get_game_state(), &GameState::Win(board.players[0]))
+        assert_eq!(board.get_game_state(), &GameState::Win(board.players[0].id))
     }
 
     #[test]
@@ -605,7 +1715,154 @@ mod tests {
             &mut board,
             &[0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 1, 2, 2, 5, 2, 5, 3, 3, 3],
         );
-        assert_eq!(board.get_game_state(), &GameState::Win(board.players[1]))
+        assert_eq!(board.get_game_state(), &GameState::Win(board.players[1].id))
+    }
+
+    #[test]
+    fn test_evaluation_symmetry() {
+        let threats = EvalWeights {
+            mode: EvalMode::Threats,
+            ..EvalWeights::default()
+        };
+        let lines: [&[usize]; 4] = [&[], &[3], &[3, 3, 2, 4, 2], &[0, 1, 2, 3, 3, 2, 1, 0, 6, 5]];
+
+        for moves in lines {
+            let mut board = Board::new_human_vs_human();
+            let mut against_ai = Board::new_human_vs_ai(1);
+            play_moves(&mut board, moves);
+            play_moves(&mut against_ai, moves);
+
+            assert_eq!(
+                board.evaluate_for(PlayerId::One),
+                -board.evaluate_for(PlayerId::Two)
+            );
+            assert_eq!(
+                board.evaluate_for_with(PlayerId::One, &threats),
+                -board.evaluate_for_with(PlayerId::Two, &threats)
+            );
+            assert_eq!(
+                board.evaluate_side_to_move(),
+                board.evaluate_for(board.get_current_player().id)
+            );
+            // Who plays the discs makes no difference
+            assert_eq!(
+                against_ai.evaluate_for(PlayerId::One),
+                board.evaluate_for(PlayerId::One)
+            );
+        }
+    }
+
+    #[test]
+    fn test_careless_misses_opponent_threes() {
+        let defaults = EvalWeights::default();
+        let careless = EvalWeights {
+            mode: EvalMode::Careless,
+            ..defaults
+        };
+
+        // O has three along the bottom with 4 open, and X nothing like it
+        let mut board = Board::new_human_vs_human();
+        play_moves(&mut board, &[0, 1, 0, 2, 6, 3]);
+        assert_eq!(
+            board.evaluate_for_with(PlayerId::One, &careless)
+                - board.evaluate_for_with(PlayerId::One, &defaults),
+            defaults.three + defaults.opponent_three
+        );
+
+        // Its own threes it still sees
+        assert_eq!(
+            board.evaluate_for_with(PlayerId::Two, &careless),
+            board.evaluate_for_with(PlayerId::Two, &defaults)
+        );
+    }
+
+    #[test]
+    fn test_seed_replays_game() {
+        let players = || {
+            Board::new(
+                PlayerType::AI(Difficulty::Beginner.ai()),
+                PlayerType::AI(Difficulty::Easy.ai()),
+            )
+        };
+        let config = GameConfig {
+            seed: 2024,
+            ..GameConfig::default()
+        };
+
+        let record = players().play_game_with_config(config, &Broadcaster::new());
+        assert_eq!(record.seed, 2024);
+        assert_ne!(record.state, GameState::InProgress);
+        assert_eq!(
+            players().play_game_with_config(config, &Broadcaster::new()),
+            record
+        );
+
+        let others: Vec<GameRecord> = (0..3)
+            .map(|seed| {
+                players().play_game_with_config(GameConfig { seed, ..config }, &Broadcaster::new())
+            })
+            .collect();
+        assert!(others.iter().any(|other| other.moves != record.moves));
+    }
+
+    #[test]
+    fn test_first_player() {
+        let play = |first_player, game_number| {
+            let mut board = Board::new(PlayerType::AI(AI::new(1)), PlayerType::AI(AI::new(1)));
+            let config = GameConfig {
+                first_player,
+                game_number,
+                ..GameConfig::default()
+            };
+            let record = board.play_game_with_config(config, &Broadcaster::new());
+            (board, record)
+        };
+
+        assert_eq!(
+            play(FirstPlayer::PlayerOne, 1).1.first_player,
+            PlayerId::One
+        );
+        assert_eq!(
+            play(FirstPlayer::Alternate, 0).1.first_player,
+            PlayerId::One
+        );
+        assert_eq!(
+            play(FirstPlayer::Alternate, 3).1.first_player,
+            PlayerId::Two
+        );
+
+        // Unless asked for something else, whoever the board has to move starts
+        let mut board = Board::new(PlayerType::AI(AI::new(1)), PlayerType::AI(AI::new(1)))
+            .with_turn_order(&[PlayerId::Two, PlayerId::One])
+            .unwrap();
+        let record = board.play_game_with_config(GameConfig::random(), &Broadcaster::new());
+        assert_eq!(record.first_player, PlayerId::Two);
+
+        // The starter's disc is at the bottom of the first column played
+        let (board, record) = play(FirstPlayer::PlayerTwo, 0);
+        assert_eq!(record.first_player, PlayerId::Two);
+        assert!(matches!(
+            board.get_board()[record.moves[0].col][ROWS - 1],
+            Cell::Player(owner) if owner == PlayerId::Two
+        ));
+    }
+
+    #[test]
+    fn test_position_key() {
+        let mut board = Board::new_human_vs_human();
+        let empty = board.position_key();
+        assert_eq!(board.moves_played(), 0);
+
+        play_moves(&mut board, &[0, 1, 2]);
+        let mut transposed = Board::new_human_vs_ai(1);
+        play_moves(&mut transposed, &[2, 1, 0]);
+        let mut swapped = Board::new_human_vs_human();
+        play_moves(&mut swapped, &[1, 0, 2]);
+
+        assert_ne!(board.position_key(), empty);
+        assert_eq!(board.position_key(), transposed.position_key());
+        assert_ne!(board.position_key(), swapped.position_key());
+        assert_eq!(board.moves_played(), 3);
     }
 
     #[test]
@@ -635,4 +1892,424 @@ mod tests {
         play_move_draw(&mut board, moves_player2);
         assert_eq!(board.get_game_state(), &GameState::Draw);
     }
+
+    #[test]
+    fn test_parse_play() {
+        assert_eq!("3".parse(), Ok(Play::drop(3)));
+        assert_eq!("p3".parse(), Ok(Play::pop(3)));
+        assert_eq!(Play::pop(3).to_string(), "p3");
+        for power in PowerUp::ALL {
+            let play = Play::power_up(power, 4);
+            assert_eq!(play.to_string().parse(), Ok(play));
+        }
+        assert_eq!("a4".parse(), Ok(Play::power_up(PowerUp::Anvil, 4)));
+        // Columns are checked against the board, which may be wider than 7
+        assert_eq!("p7".parse(), Ok(Play::pop(7)));
+        assert!("px".parse::<Play>().is_err());
+        assert!("x".parse::<Play>().is_err());
+    }
+
+    #[test]
+    fn test_pop() {
+        let mut classic = Board::new_human_vs_human();
+        play_moves(&mut classic, &[0, 0, 1, 2]);
+        assert!(!classic.valid_play(Play::pop(0)));
+
+        let mut board = Board::new_human_vs_human().with_variant(Variant::PopOut);
+        play_moves(&mut board, &[0, 0, 1, 2]);
+        // Only your own discs, and only from the bottom row
+        assert!(board.valid_play(Play::pop(0)));
+        assert!(board.valid_play(Play::pop(1)));
+        assert!(!board.valid_play(Play::pop(2)));
+        assert!(!board.valid_play(Play::pop(3)));
+        assert_eq!(board.legal_plays().len(), COLS + 2);
+        // Off the board, even asked for directly
+        assert_eq!(board.pop_disc(COLS), None);
+        assert_eq!(board.drop_disc(COLS), None);
+        assert_eq!(board.bomb(COLS), None);
+        assert_eq!(board.drop_anvil(COLS), None);
+
+        let popped = board.play(Play::pop(0)).unwrap();
+        assert_eq!(popped.kind, MoveKind::Pop);
+        assert_eq!(
+            popped.position,
+            Position {
+                row: ROWS - 1,
+                col: 0
+            }
+        );
+
+        // Player 2's disc fell into the bottom row
+        assert!(matches!(
+            board.get_board()[0][ROWS - 1],
+            Cell::Player(owner) if owner == PlayerId::Two
+        ));
+        assert_eq!(board.get_board()[0][ROWS - 2], Cell::Empty);
+        assert_eq!(board.get_current_player().id, PlayerId::Two);
+    }
+
+    /*
+     * _ _ _ _ _ _ _
+     * _ _ _ _ _ _ _
+     * _ _ _ _ _ _ _
+     * _ _ _ X _ _ _
+     * _ _ _ O X X X
+     * O O O X O O O
+     *
+     * Popping column 3 gives both players a four.
+     */
+    fn double_four_position() -> Board {
+        let mut board = Board::new_human_vs_human().with_variant(Variant::PopOut);
+        let (x, o) = (PlayerId::One, PlayerId::Two);
+        for col in [0, 1, 2, 4, 5, 6] {
+            board.test_drop_col(col, ROWS - 1, o);
+        }
+        board.test_drop_col(3, ROWS - 1, x);
+        board.test_drop_col(3, ROWS - 2, o);
+        board.test_drop_col(3, ROWS - 3, x);
+        for col in 4..COLS {
+            board.test_drop_col(col, ROWS - 2, x);
+        }
+        board
+    }
+
+    #[test]
+    fn test_pop_making_two_fours_wins_for_popper() {
+        let mut board = double_four_position();
+        assert_eq!(board.get_game_state(), &GameState::InProgress);
+
+        board.play(Play::pop(3));
+        assert_eq!(board.get_game_state(), &GameState::Win(PlayerId::One));
+    }
+
+    #[test]
+    fn test_ai_pops_to_win() {
+        let board = double_four_position();
+        let ai = AI {
+            depth: 2,
+            ..AI::default()
+        };
+        assert_eq!(ai.best_play(&board), Some(Play::pop(3)));
+    }
+
+    #[test]
+    fn test_repetition_draw() {
+        let mut board = Board::new_human_vs_human().with_variant(Variant::PopOut);
+        let cycle = [Play::drop(0), Play::drop(1), Play::pop(0), Play::pop(1)];
+
+        // The position after the first drop comes up for the third time on the 9th play
+        for &play in cycle.iter().cycle().take(8) {
+            board.play(play).unwrap();
+        }
+        assert_eq!(board.get_game_state(), &GameState::InProgress);
+        board.play(cycle[0]).unwrap();
+        assert_eq!(board.get_game_state(), &GameState::Draw);
+    }
+
+    /// Column by column, so every bottom disc is Player 1's and the bottom row is a four.
+    fn filled_pop_ten() -> Board {
+        let mut board = Board::new_human_vs_human().with_variant(Variant::PopTen);
+        for col in 0..COLS {
+            for _ in 0..ROWS {
+                board.play_move(col).unwrap();
+            }
+        }
+        board
+    }
+
+    #[test]
+    fn test_pop_ten_turns() {
+        let mut board = filled_pop_ten();
+        // Fours made while filling do not win
+        assert_eq!(board.get_game_state(), &GameState::InProgress);
+        assert!(!board.valid_play(Play::drop(0)));
+        assert_eq!(board.legal_plays().len(), COLS);
+
+        // Part of the bottom row four, so it is kept
+        board.play(Play::pop(0)).unwrap();
+        assert_eq!(board.scores(), Some(vec![1, 0]));
+        assert_eq!(board.get_current_player().id, PlayerId::Two);
+
+        // Not part of a four, so Player 2 has to put it back
+        assert_eq!(board.legal_plays(), vec![Play::pop(0)]);
+        board.play(Play::pop(0)).unwrap();
+        assert_eq!(board.scores(), Some(vec![1, 0]));
+        assert_eq!(board.get_current_player().id, PlayerId::Two);
+        assert_eq!(board.legal_plays(), vec![Play::drop(0)]);
+
+        board.play(Play::drop(0)).unwrap();
+        assert_eq!(board.get_current_player().id, PlayerId::One);
+    }
+
+    #[test]
+    fn test_pop_ten_win() {
+        let mut board = filled_pop_ten();
+        board.pop_ten.captured = [rules::POP_TEN_TARGET - 1, 0];
+
+        board.play(Play::pop(3)).unwrap();
+        assert_eq!(board.get_game_state(), &GameState::Win(PlayerId::One));
+        assert_eq!(board.scores(), Some(vec![rules::POP_TEN_TARGET, 0]));
+        assert!(board.to_string().contains("Score: X 10 - O 0"));
+        assert_eq!(Board::new_human_vs_human().scores(), None);
+    }
+
+    #[test]
+    fn test_ai_plays_pop_ten() {
+        let mut board = filled_pop_ten();
+        let ai = AI {
+            depth: 3,
+            ..AI::default()
+        };
+        // Ends in ten captures, a repetition, or with nothing left to pop
+        for _ in 0..200 {
+            if board.get_game_state() != &GameState::InProgress {
+                break;
+            }
+            let play = ai.best_play(&board).unwrap();
+            board.play(play).unwrap();
+        }
+        assert_ne!(board.get_game_state(), &GameState::InProgress);
+    }
+
+    #[test]
+    fn test_cylinder_wraps() {
+        // X on the bottom of columns 5, 6, 0 and 1, O stacked above
+        let moves = [5, 5, 6, 6, 0, 0, 1];
+        let mut classic = Board::new_human_vs_human();
+        let mut cylinder = Board::new_human_vs_human().with_variant(Variant::Cylinder);
+        play_moves(&mut classic, &moves);
+        play_moves(&mut cylinder, &moves);
+
+        assert_eq!(classic.get_game_state(), &GameState::InProgress);
+        assert_eq!(cylinder.get_game_state(), &GameState::Win(PlayerId::One));
+
+        // Every cell starts a row and a pair of diagonals when they can wrap
+        assert_eq!(classic.windows().len(), 69);
+        assert_eq!(cylinder.windows().len(), ROWS * COLS + 21 + 2 * 3 * COLS);
+    }
+
+    #[test]
+    fn test_ai_blocks_wrapped_four() {
+        // X has 6, 0 and 1, and only column 5 finishes it
+        let mut board = Board::new_human_vs_human().with_variant(Variant::Cylinder);
+        play_moves(&mut board, &[6, 2, 0, 4, 1]);
+        let ai = AI {
+            depth: 2,
+            ..AI::default()
+        };
+        assert_eq!(ai.best_move(&board), Some(5));
+    }
+
+    fn three_humans() -> Board {
+        Board::new_multiplayer(&vec![PlayerType::Human; 3])
+    }
+
+    #[test]
+    fn test_three_player_turns() {
+        let mut board = three_humans();
+        play_moves(&mut board, &[0, 1, 2]);
+
+        assert_eq!(board.get_current_player().id, PlayerId::One);
+        let bottom = board.to_string().lines().nth(ROWS).unwrap().to_string();
+        assert_eq!(bottom, "X O # _ _ _ _ ");
+
+        let order: Vec<PlayerId> = "3,1,2".split(',').map(|id| id.parse().unwrap()).collect();
+        let mut board = three_humans().with_turn_order(&order).unwrap();
+        assert_eq!(board.get_current_player().id, PlayerId::Three);
+        play_moves(&mut board, &[0, 0]);
+        assert_eq!(board.get_current_player().id, PlayerId::Two);
+
+        // Each player once, and only the players in the game
+        let turns = |order: &[PlayerId]| three_humans().with_turn_order(order).map(|_| ());
+        assert!(turns(&[PlayerId::One, PlayerId::One, PlayerId::Two]).is_err());
+        assert!(turns(&[PlayerId::One, PlayerId::Two]).is_err());
+        assert!(turns(&[PlayerId::One, PlayerId::Two, PlayerId::Four]).is_err());
+        assert!("5".parse::<PlayerId>().is_err());
+        assert!("0".parse::<PlayerId>().is_err());
+    }
+
+    #[test]
+    fn test_first_to_connect() {
+        // Player 3 fills column 6 while the others spread out
+        let mut board = three_humans();
+        play_moves(&mut board, &[0, 1, 6, 0, 1, 6, 2, 3, 6, 4, 5, 6]);
+
+        assert_eq!(board.get_game_state(), &GameState::Win(PlayerId::Three));
+    }
+
+    #[test]
+    fn test_board_size() {
+        let size: BoardSize = "9x7:5".parse().unwrap();
+        assert_eq!((size.cols, size.rows, size.win_length), (9, 7, 5));
+        assert_eq!(size.to_string(), "9x7:5");
+        assert_eq!("7x6".parse(), Ok(BoardSize::default()));
+        assert!("7x6:8".parse::<BoardSize>().is_err());
+        assert!("7x6:1".parse::<BoardSize>().is_err());
+        assert!("x6".parse::<BoardSize>().is_err());
+        assert!("0x5:2".parse::<BoardSize>().is_err());
+        assert!("7x0".parse::<BoardSize>().is_err());
+        assert!("7x65".parse::<BoardSize>().is_err());
+    }
+
+    #[test]
+    fn test_connect_five() {
+        let mut board = Board::new_human_vs_human().with_size("9x7:5".parse().unwrap());
+        // O stacks on X along the bottom, so O also has four when X makes five
+        play_moves(&mut board, &[0, 0, 1, 1, 2, 2, 3, 3]);
+        assert_eq!(board.get_game_state(), &GameState::InProgress);
+
+        assert!(board.play_move(8).is_some());
+        play_moves(&mut board, &[8, 4]);
+        assert_eq!(board.get_game_state(), &GameState::Win(PlayerId::One));
+        assert!(board.to_string().ends_with("0 1 2 3 4 5 6 7 8\n\n"));
+    }
+
+    const WALL: &str = "
+        _ _ _ _ _ _ _
+        _ _ _ * _ _ _
+        _ _ _ * _ _ _
+        _ _ _ * _ _ _
+        _ _ _ X _ _ _
+        _ _ O X _ _ _";
+
+    #[test]
+    fn test_layout() {
+        let layout = WALL.parse().unwrap();
+        let mut board = Board::new_human_vs_human().with_layout(&layout).unwrap();
+
+        assert!(board.has_blocked_cells());
+        assert_eq!(board.moves_played(), 3);
+        assert!(board.to_string().contains("_ _ _ * _ _ _"));
+        // Nothing can be made through the wall
+        assert!(board.windows().len() < 69);
+
+        let landed = board.play_move(3).unwrap();
+        assert_eq!(landed.position, Position { row: 0, col: 3 });
+        assert!(!board.valid_move(3));
+
+        let layout = format!("to-move O\n{}", WALL).parse().unwrap();
+        let board = Board::new_human_vs_human().with_layout(&layout).unwrap();
+        assert_eq!(board.get_current_player().id, PlayerId::Two);
+    }
+
+    #[test]
+    fn test_layout_errors() {
+        let board = || Board::new_human_vs_human();
+        let parse = |text: &str| text.parse::<Layout>().unwrap();
+
+        assert!(board().with_layout(&parse("_ X\n_ _")).is_err());
+        assert!(board().with_layout(&parse("_ _\n# _")).is_err());
+        assert!(board().with_layout(&parse("_ _ _ _\nX X X X")).is_err());
+        assert_eq!(
+            board().with_layout(&parse("_ _\n_ _")).err(),
+            Some(FormatError::whole(
+                "a 2 by 2 layout has no room for 4 in a row"
+            ))
+        );
+        assert!(board()
+            .with_layout(&parse("_ _ _ _\n* * * *\n_ _ _ _"))
+            .is_ok());
+        assert!(board().with_layout(&parse("to-move #\n_ _")).is_err());
+
+        // Layouts built in code are checked too
+        let mut off_board = parse("_ _ _ _\n_ _ _ _");
+        off_board.blocked.push(Position { row: 0, col: 4 });
+        assert!(board().with_layout(&off_board).is_err());
+        off_board.blocked.clear();
+        off_board
+            .discs
+            .push((Position { row: 2, col: 0 }, PlayerId::One));
+        assert!(board().with_layout(&off_board).is_err());
+        let empty = Layout {
+            rows: 0,
+            cols: 0,
+            ..parse("_")
+        };
+        assert!(board().with_layout(&empty).is_err());
+    }
+
+    #[test]
+    fn test_pop_under_blocked_cell() {
+        let layout = "_ _\n* _\nO _\nX _".parse().unwrap();
+        let mut board = Board::new_human_vs_human()
+            .with_variant(Variant::PopOut)
+            .with_layout(&layout)
+            .unwrap();
+
+        assert!(board.play(Play::pop(0)).is_some());
+        let col = &board.get_board()[0];
+        assert_eq!(col[1], Cell::Blocked);
+        assert_eq!(col[2], Cell::Empty);
+        assert!(matches!(col[3], Cell::Player(owner) if owner == PlayerId::Two));
+    }
+
+    fn tic_tac_toe() -> Board {
+        Board::new_human_vs_human()
+            .with_variant(Variant::FreePlacement)
+            .with_size("3x3:3".parse().unwrap())
+    }
+
+    fn place_all(board: &mut Board, cells: &[(usize, usize)]) {
+        for &(col, row) in cells {
+            assert!(board.place(Position { row, col }).is_some());
+        }
+    }
+
+    #[test]
+    fn test_free_placement() {
+        let mut board = tic_tac_toe();
+        assert_eq!("2,0".parse(), Ok(Play::place(Position { row: 0, col: 2 })));
+        assert_eq!(Play::place(Position { row: 0, col: 2 }).to_string(), "2,0");
+
+        // Discs stay where they are put, even at the top
+        place_all(&mut board, &[(0, 0), (1, 0), (1, 1)]);
+        assert!(board.place(Position { row: 0, col: 0 }).is_none());
+        assert!(!board.valid_move(2));
+        assert!(board.to_string().starts_with("\nX O _ 0\n_ X _ 1\n"));
+
+        place_all(&mut board, &[(2, 0), (2, 2)]);
+        assert_eq!(board.get_game_state(), &GameState::Win(PlayerId::One));
+
+        // X O X / X O O / O X X fills the board with no line
+        let mut board = tic_tac_toe();
+        place_all(
+            &mut board,
+            &[
+                (0, 0),
+                (1, 0),
+                (2, 0),
+                (1, 1),
+                (0, 1),
+                (2, 1),
+                (1, 2),
+                (0, 2),
+                (2, 2),
+            ],
+        );
+        assert_eq!(board.get_game_state(), &GameState::Draw);
+    }
+
+    #[test]
+    fn test_ai_plays_tic_tac_toe() {
+        let ai = AI {
+            depth: 9,
+            ..AI::default()
+        };
+
+        // O must block X's top row
+        let mut board = tic_tac_toe();
+        place_all(&mut board, &[(0, 0), (1, 1), (1, 0)]);
+        assert_eq!(
+            ai.best_play(&board),
+            Some(Play::place(Position { row: 0, col: 2 }))
+        );
+
+        // Perfect play from the start is a draw
+        let mut board = tic_tac_toe();
+        while board.get_game_state() == &GameState::InProgress {
+            let play = ai.best_play(&board).unwrap();
+            board.play(play);
+        }
+        assert_eq!(board.get_game_state(), &GameState::Draw);
+    }
 }
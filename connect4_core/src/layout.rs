@@ -0,0 +1,162 @@
+use crate::format::FormatError;
+use crate::game::Position;
+use crate::player::PlayerId;
+use std::str::FromStr;
+use std::{fmt, fs, io};
+
+/// What blocks a cell in a layout file.
+pub const BLOCKED: char = '*';
+/// Starts the line saying whose turn it is in a layout file.
+const TO_MOVE: &str = "to-move";
+
+/**
+ * A starting position for `Board::with_layout`: cells blocked for the whole
+ * game and discs already played. Layout files draw the board the way it is
+ * printed, top row first, with `_` for an empty cell, `*` for a blocked one
+ * and the players' symbols for their discs. Spaces between cells are
+ * optional, and blank lines and lines starting with `;` are skipped. A
+ * `to-move` line with a player's symbol says whose turn it is, otherwise
+ * the board keeps its own first player:
+ *
+ *   ; A wall in the middle
+ *   to-move O
+ *   _ _ _ _ _ _ _
+ *   _ _ _ * _ _ _
+ *   _ _ _ * _ _ _
+ *   _ _ _ * _ _ _
+ *   _ _ _ X _ _ _
+ *   _ _ O X _ _ _
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub struct Layout {
+    pub rows: usize,
+    pub cols: usize,
+    pub blocked: Vec<Position>,
+    pub discs: Vec<(Position, PlayerId)>,
+    pub to_move: Option<PlayerId>,
+}
+
+impl Layout {
+    pub fn load(path: &str) -> io::Result<Layout> {
+        Ok(fs::read_to_string(path)?.parse()?)
+    }
+}
+
+impl FromStr for Layout {
+    type Err = FormatError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut layout = Layout {
+            rows: 0,
+            cols: 0,
+            blocked: Vec::new(),
+            discs: Vec::new(),
+            to_move: None,
+        };
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            let error = |message: String| FormatError::at(number, message);
+
+            if let Some(symbol) = line.strip_prefix(TO_MOVE) {
+                let symbol = symbol.trim();
+                if layout.to_move.is_some() {
+                    return Err(error(format!("{} given twice", TO_MOVE)));
+                }
+                let id = PlayerId::ALL
+                    .into_iter()
+                    .find(|id| symbol.chars().eq([id.symbol()]))
+                    .ok_or_else(|| error(format!("unknown player: {}", symbol)))?;
+                layout.to_move = Some(id);
+                continue;
+            }
+
+            let row = layout.rows;
+            let cells: Vec<char> = line.chars().filter(|ch| !ch.is_whitespace()).collect();
+            if row > 0 && cells.len() != layout.cols {
+                return Err(error(format!(
+                    "expected {} cells, found {}",
+                    layout.cols,
+                    cells.len()
+                )));
+            }
+            for (col, &ch) in cells.iter().enumerate() {
+                let position = Position { row, col };
+                match ch {
+                    '_' => {}
+                    BLOCKED => layout.blocked.push(position),
+                    _ => match PlayerId::ALL.into_iter().find(|id| id.symbol() == ch) {
+                        Some(id) => layout.discs.push((position, id)),
+                        None => return Err(error(format!("unknown cell: {}", ch))),
+                    },
+                }
+            }
+            layout.cols = cells.len();
+            layout.rows += 1;
+        }
+
+        if layout.rows == 0 || layout.cols == 0 {
+            return Err(FormatError::whole("empty layout"));
+        }
+        Ok(layout)
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(id) = self.to_move {
+            writeln!(f, "{} {}", TO_MOVE, id.symbol())?;
+        }
+        for row in 0..self.rows {
+            let cells: Vec<String> = (0..self.cols)
+                .map(|col| {
+                    let position = Position { row, col };
+                    let disc = self.discs.iter().find(|(at, _)| *at == position);
+                    match disc {
+                        Some((_, id)) => id.symbol(),
+                        None if self.blocked.contains(&position) => BLOCKED,
+                        None => '_',
+                    }
+                    .to_string()
+                })
+                .collect();
+            writeln!(f, "{}", cells.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_layout() {
+        let text = "; Player 1 on a blocked cell\n\n_ _ _\nX _ _\n* O _\n";
+        let layout: Layout = text.parse().unwrap();
+
+        assert_eq!((layout.cols, layout.rows), (3, 3));
+        assert_eq!(layout.blocked, [Position { row: 2, col: 0 }]);
+        assert_eq!(
+            layout.discs[0],
+            (Position { row: 1, col: 0 }, PlayerId::One)
+        );
+        assert_eq!(layout.to_move, None);
+        assert_eq!(layout.to_string(), "_ _ _\nX _ _\n* O _\n");
+        assert_eq!(layout.to_string().parse(), Ok(layout));
+
+        let layout: Layout = "to-move O\n_ _\nX _".parse().unwrap();
+        assert_eq!(layout.to_move, Some(PlayerId::Two));
+        assert_eq!(layout.to_string(), "to-move O\n_ _\nX _\n");
+        assert_eq!(layout.to_string().parse(), Ok(layout));
+
+        assert!("_ _\n_ _ _".parse::<Layout>().is_err());
+        assert!("to-move Q\n_ _".parse::<Layout>().is_err());
+        assert!("to-move X\nto-move O\n_ _".parse::<Layout>().is_err());
+        assert!("_ ?".parse::<Layout>().is_err());
+        assert!("; nothing".parse::<Layout>().is_err());
+    }
+}
@@ -0,0 +1,20 @@
+pub mod agent;
+pub mod ai;
+pub mod book;
+pub mod difficulty;
+pub mod engine;
+pub mod eval;
+pub mod format;
+pub mod game;
+pub mod layout;
+pub mod mcts;
+pub mod player;
+pub mod ponder;
+pub mod protocol;
+pub mod rules;
+pub mod series;
+pub mod spectator;
+pub mod sprt;
+pub mod threats;
+pub mod tournament;
+pub mod tune;
@@ -1,9 +1,6 @@
-use crate::game::Board;
-mod ai;
-mod game;
-mod player;
+use connect4::game::Board;
 
 fn main() {
-    let mut board = Board::new_human_vs_human();
+    let board = Board::new_human_vs_human();
     println!("{}", board);
 }
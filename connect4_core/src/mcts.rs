@@ -0,0 +1,160 @@
+use crate::agent::{Agent, AgentError};
+use crate::game::{Board, GameState, Play};
+use crate::player::PlayerId;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+/// How strongly UCT favours rarely tried moves over ones that have scored well.
+const EXPLORATION: f64 = std::f64::consts::SQRT_2;
+
+/**
+ * Monte Carlo tree search: instead of an evaluation it plays `playouts`
+ * random games from the position, growing a tree towards the moves that
+ * win them (UCT), and plays the move tried most. A very different kind of
+ * opponent from `AI` to test against, and one that knows nothing about
+ * Connect 4 beyond the rules.
+ */
+pub struct Mcts {
+    pub playouts: usize,
+    rng: StdRng,
+}
+
+/// One position in the tree, reached by `play`.
+struct Node {
+    play: Option<Play>,
+    /// Who made `play`, and so whose wins count for this node.
+    mover: Option<PlayerId>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<Play>,
+    visits: u32,
+    /// A win counts 1 and a draw half.
+    score: f64,
+}
+
+impl Mcts {
+    pub fn new(playouts: usize, seed: u64) -> Self {
+        Mcts {
+            playouts: playouts.max(1),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// The most tried move after all the playouts, or `None` if the game is over.
+    pub fn best_play(&mut self, board: &Board) -> Option<Play> {
+        if *board.get_game_state() != GameState::InProgress {
+            return None;
+        }
+        let mut tree = vec![Node {
+            play: None,
+            mover: None,
+            parent: None,
+            children: Vec::new(),
+            untried: board.legal_plays(),
+            visits: 0,
+            score: 0.0,
+        }];
+
+        for _ in 0..self.playouts {
+            let mut board = board.clone();
+            let mut node = 0;
+
+            // Selection: follow the best child while every move has been tried
+            while tree[node].untried.is_empty() && !tree[node].children.is_empty() {
+                node = select(&tree, node);
+                board.play(tree[node].play.unwrap());
+            }
+
+            // Expansion: one untried move gets a node of its own
+            if !tree[node].untried.is_empty() {
+                let index = self.rng.gen_range(0..tree[node].untried.len());
+                let play = tree[node].untried.swap_remove(index);
+                let mover = board.get_current_player().id;
+                board.play(play);
+                tree.push(Node {
+                    play: Some(play),
+                    mover: Some(mover),
+                    parent: Some(node),
+                    children: Vec::new(),
+                    untried: board.legal_plays(),
+                    visits: 0,
+                    score: 0.0,
+                });
+                let child = tree.len() - 1;
+                tree[node].children.push(child);
+                node = child;
+            }
+
+            // Simulation: random moves to the end of the game
+            while *board.get_game_state() == GameState::InProgress {
+                let Some(&play) = board.legal_plays().choose(&mut self.rng) else {
+                    break;
+                };
+                board.play(play);
+            }
+
+            // Backpropagation
+            let state = *board.get_game_state();
+            let mut current = Some(node);
+            while let Some(index) = current {
+                let node = &mut tree[index];
+                node.visits += 1;
+                node.score += match (state, node.mover) {
+                    (GameState::Win(winner), Some(mover)) if winner == mover => 1.0,
+                    (GameState::Win(_), _) => 0.0,
+                    _ => 0.5,
+                };
+                current = node.parent;
+            }
+        }
+
+        tree[0]
+            .children
+            .iter()
+            .max_by_key(|&&child| tree[child].visits)
+            .and_then(|&child| tree[child].play)
+    }
+}
+
+/// The child of `node` with the best upper confidence bound.
+fn select(tree: &[Node], node: usize) -> usize {
+    let log_visits = (tree[node].visits as f64).ln();
+    let uct = |child: usize| {
+        let child = &tree[child];
+        let visits = child.visits as f64;
+        child.score / visits + EXPLORATION * (log_visits / visits).sqrt()
+    };
+    *tree[node]
+        .children
+        .iter()
+        .max_by(|&&a, &&b| uct(a).total_cmp(&uct(b)))
+        .unwrap()
+}
+
+impl Agent for Mcts {
+    fn name(&self) -> String {
+        format!("MCTS ({} playouts)", self.playouts)
+    }
+
+    fn choose_move(&mut self, board: &Board, _plays: &[Play]) -> Result<Play, AgentError> {
+        self.best_play(board).ok_or(AgentError::NoMove)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::board_from_moves;
+
+    #[test]
+    fn test_mcts_finds_the_win() {
+        // Player 1 has three along the bottom, and 3 wins outright
+        let board = board_from_moves(&[0, 0, 1, 1, 2, 2]).unwrap();
+        assert_eq!(Mcts::new(2_000, 1).best_play(&board), Some(Play::drop(3)));
+
+        // The same seed plays the same move
+        let play = |seed| Mcts::new(50, seed).best_play(&Board::new_human_vs_human());
+        assert_eq!(play(7), play(7));
+    }
+}
@@ -1,31 +1,82 @@
 use std::fmt;
+use std::str::FromStr;
 
+use crate::agent::{Agent, SharedAgent};
 use crate::ai::AI;
+use crate::protocol::ProtocolError;
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
 pub enum PlayerId {
     One,
     Two,
+    Three,
+    Four,
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum PlayerType {
     Human,
     AI(AI),
+    /// Any other `Agent`, such as an `ExternalEngine`.
+    Agent(SharedAgent),
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct Player {
     pub id: PlayerId,
     pub kind: PlayerType,
 }
 
+impl PlayerId {
+    /// Every player there can be, in the usual turn order.
+    pub const ALL: [PlayerId; 4] = [
+        PlayerId::One,
+        PlayerId::Two,
+        PlayerId::Three,
+        PlayerId::Four,
+    ];
+
+    /// The other player of a two-player game. Panics for Players 3 and 4.
+    pub fn opponent(self) -> PlayerId {
+        match self {
+            PlayerId::One => PlayerId::Two,
+            PlayerId::Two => PlayerId::One,
+            PlayerId::Three | PlayerId::Four => panic!("Player {} has no one opponent", self),
+        }
+    }
+
+    /// 0 for Player 1 up to 3 for Player 4.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// What the player's discs look like on the board.
+    pub fn symbol(self) -> char {
+        match self {
+            PlayerId::One => 'X',
+            PlayerId::Two => 'O',
+            PlayerId::Three => '#',
+            PlayerId::Four => '@',
+        }
+    }
+}
+
 impl fmt::Display for PlayerId {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        return match self {
-            PlayerId::One => write!(f, "1"),
-            PlayerId::Two => write!(f, "2"),
-        };
+        write!(f, "{}", self.index() + 1)
+    }
+}
+
+/// A player by number, 1 to 4, as `Display` writes it.
+impl FromStr for PlayerId {
+    type Err = ProtocolError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        text.parse::<usize>()
+            .ok()
+            .and_then(|number| number.checked_sub(1))
+            .and_then(|index| PlayerId::ALL.get(index).copied())
+            .ok_or_else(|| ProtocolError(format!("unknown player: {}", text)))
     }
 }
 
@@ -34,13 +85,11 @@ impl fmt::Display for Player {
         write!(
             f,
             "Player {} is a {}",
-            match self.id {
-                PlayerId::One => 1,
-                PlayerId::Two => 2,
-            },
+            self.id,
             match &self.kind {
-                PlayerType::Human => "Human",
-                PlayerType::AI(_ai) => "AI",
+                PlayerType::Human => "Human".to_string(),
+                PlayerType::AI(_ai) => "AI".to_string(),
+                PlayerType::Agent(agent) => agent.name(),
             }
         )
     }
@@ -0,0 +1,140 @@
+use crate::ai::{is_proven, SearchInfo, SearchLimits, AI};
+use crate::game::{Board, GameState, Play};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/**
+ * Searches while the opponent is thinking. For each reply the opponent could
+ * make, the position after it is searched to the AI's full depth in a
+ * background thread, the most likely reply first. When the real reply comes
+ * in, `finish` hands back the finished search for it, if there is one.
+ *
+ * Dropping a `Ponder` stops the thread and waits for it.
+ */
+pub struct Ponder {
+    stop: Arc<AtomicBool>,
+    results: Arc<Mutex<HashMap<Play, SearchInfo>>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Ponder {
+    /// Starts pondering on `board`, where it is the opponent of `ai` to move.
+    pub fn start(ai: AI, board: &Board) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let results = Arc::new(Mutex::new(HashMap::new()));
+        let board = board.clone();
+
+        let handle = {
+            let (stop, results) = (Arc::clone(&stop), Arc::clone(&results));
+            thread::spawn(move || {
+                let mut replies = board.legal_plays();
+
+                // A quick look at the opponent's options to guess their move
+                let guess = AI {
+                    depth: ai.depth.min(4),
+                    threads: 1,
+                    ..ai.clone()
+                };
+                if let Some(predicted) = guess.analyse(&board).best_move {
+                    replies.retain(|&play| play != predicted);
+                    replies.insert(0, predicted);
+                }
+
+                for reply in replies {
+                    let mut child = board.clone();
+                    child.play(reply);
+                    if *child.get_game_state() != GameState::InProgress {
+                        continue;
+                    }
+
+                    let info = ai.search(&child, SearchLimits::default(), &stop, |_| {});
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    if info.depth >= ai.depth || is_proven(info.score) {
+                        results.lock().unwrap().insert(reply, info);
+                    }
+                }
+            })
+        };
+
+        Ponder {
+            stop,
+            results,
+            handle: Some(handle),
+        }
+    }
+
+    /// Replies searched to the full depth so far.
+    pub fn finished(&self) -> usize {
+        self.results.lock().unwrap().len()
+    }
+
+    /// Stops pondering and returns the search for the position after `reply`, if it finished.
+    pub fn finish(mut self, reply: Play) -> Option<SearchInfo> {
+        self.cancel();
+        self.results.lock().unwrap().remove(&reply)
+    }
+
+    fn cancel(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.join().expect("ponder thread panicked");
+        }
+    }
+}
+
+impl Drop for Ponder {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::COLS;
+    use std::time::{Duration, Instant};
+
+    fn wait_for(ponder: &Ponder, replies: usize) {
+        let started = Instant::now();
+        while ponder.finished() < replies {
+            assert!(started.elapsed() < Duration::from_secs(30));
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_ponder_matches_search() {
+        let ai = AI {
+            depth: 4,
+            ..AI::default()
+        };
+        let board = Board::new_human_vs_human();
+
+        let ponder = Ponder::start(ai.clone(), &board);
+        wait_for(&ponder, COLS);
+        let pondered = ponder.finish(Play::drop(2)).unwrap();
+
+        let mut after = board.clone();
+        after.play_move(2);
+        assert_eq!(pondered.best_move, ai.best_play(&after));
+        assert_eq!(pondered.depth, ai.depth);
+    }
+
+    #[test]
+    fn test_ponder_cancels() {
+        let ai = AI {
+            depth: 42,
+            ..AI::default()
+        };
+        let ponder = Ponder::start(ai, &Board::new_human_vs_human());
+        thread::sleep(Duration::from_millis(20));
+
+        let started = Instant::now();
+        assert_eq!(ponder.finish(Play::drop(3)), None);
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
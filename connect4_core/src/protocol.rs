@@ -0,0 +1,292 @@
+use crate::ai::{SearchInfo, SearchLimits};
+use crate::game::{Board, GameState, Play, COLS};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/**
+ * The text protocol spoken by `connect4_engine` over stdin/stdout, one
+ * command or response per line. Columns are numbered 0-6 like in the game,
+ * and a game is written as the columns played in order, e.g. `3324`. Only
+ * classic games can be set up, but moves in responses are written like any
+ * `Play`, so a pop would be `p3`.
+ *
+ *   isready                      -> readyok
+ *   position startpos [moves 3324]
+ *   position moves 3324
+ *   go [depth 10] [movetime 500] -> info ... / bestmove 3
+ *   stop                         -> bestmove 3
+ *   quit
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub enum Command {
+    IsReady,
+    Position(Vec<usize>),
+    Go(SearchLimits),
+    Stop,
+    Quit,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Response {
+    ReadyOk,
+    Info(SearchInfo),
+    BestMove(Option<Play>),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct ProtocolError(pub String);
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+fn parse_number<T: FromStr>(token: Option<&str>, what: &str) -> Result<T, ProtocolError> {
+    let token = token.ok_or_else(|| ProtocolError(format!("missing value for {}", what)))?;
+    token
+        .parse()
+        .map_err(|_| ProtocolError(format!("invalid value for {}: {}", what, token)))
+}
+
+/// Parses a move string like `3324` into columns.
+pub fn parse_moves(moves: &str) -> Result<Vec<usize>, ProtocolError> {
+    moves
+        .chars()
+        .map(|ch| match ch.to_digit(10) {
+            Some(col) if (col as usize) < COLS => Ok(col as usize),
+            _ => Err(ProtocolError(format!("invalid column in moves: {}", ch))),
+        })
+        .collect()
+}
+
+pub fn format_moves(moves: &[usize]) -> String {
+    moves.iter().map(|col| col.to_string()).collect()
+}
+
+/// Like `format_moves`, with pops written as `p3`.
+pub fn format_plays(plays: &[Play]) -> String {
+    plays.iter().map(|play| play.to_string()).collect()
+}
+
+/// Plays `moves` from the empty board, failing on the first illegal one or on moves after the end.
+pub fn board_from_moves(moves: &[usize]) -> Result<Board, ProtocolError> {
+    let mut board = Board::new_human_vs_human();
+    for (ply, &col) in moves.iter().enumerate() {
+        if *board.get_game_state() != GameState::InProgress {
+            return Err(ProtocolError(format!(
+                "game is already over at ply {}",
+                ply
+            )));
+        }
+        if board.play_move(col).is_none() {
+            return Err(ProtocolError(format!(
+                "illegal move {} at ply {}",
+                col, ply
+            )));
+        }
+    }
+    Ok(board)
+}
+
+impl FromStr for Command {
+    type Err = ProtocolError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some("isready") => Ok(Command::IsReady),
+            Some("stop") => Ok(Command::Stop),
+            Some("quit") => Ok(Command::Quit),
+            Some("position") => {
+                let mut token = tokens.next();
+                if token == Some("startpos") {
+                    token = tokens.next();
+                }
+                match token {
+                    None => Ok(Command::Position(Vec::new())),
+                    // An empty game may be sent as "position moves"
+                    Some("moves") => Ok(Command::Position(parse_moves(
+                        tokens.next().unwrap_or_default(),
+                    )?)),
+                    Some(token) => Err(ProtocolError(format!("unexpected token: {}", token))),
+                }
+            }
+            Some("go") => {
+                let mut limits = SearchLimits::default();
+                while let Some(token) = tokens.next() {
+                    match token {
+                        "depth" => limits.depth = Some(parse_number(tokens.next(), "depth")?),
+                        "movetime" => {
+                            let millis = parse_number(tokens.next(), "movetime")?;
+                            limits.movetime = Some(Duration::from_millis(millis));
+                        }
+                        _ => return Err(ProtocolError(format!("unexpected token: {}", token))),
+                    }
+                }
+                Ok(Command::Go(limits))
+            }
+            Some(command) => Err(ProtocolError(format!("unknown command: {}", command))),
+            None => Err(ProtocolError("empty command".to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::IsReady => write!(f, "isready"),
+            Command::Stop => write!(f, "stop"),
+            Command::Quit => write!(f, "quit"),
+            Command::Position(moves) if moves.is_empty() => write!(f, "position startpos"),
+            Command::Position(moves) => {
+                write!(f, "position startpos moves {}", format_moves(moves))
+            }
+            Command::Go(limits) => {
+                write!(f, "go")?;
+                if let Some(depth) = limits.depth {
+                    write!(f, " depth {}", depth)?;
+                }
+                if let Some(movetime) = limits.movetime {
+                    write!(f, " movetime {}", movetime.as_millis())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl FromStr for Response {
+    type Err = ProtocolError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut tokens = line.split_whitespace();
+
+        match tokens.next() {
+            Some("readyok") => Ok(Response::ReadyOk),
+            Some("bestmove") => match tokens.next() {
+                Some("none") => Ok(Response::BestMove(None)),
+                token => Ok(Response::BestMove(Some(parse_number(token, "bestmove")?))),
+            },
+            Some("info") => {
+                let mut info = SearchInfo {
+                    depth: 0,
+                    score: 0,
+                    best_move: None,
+                    pv: Vec::new(),
+                    nodes: 0,
+                };
+                while let Some(token) = tokens.next() {
+                    match token {
+                        "depth" => info.depth = parse_number(tokens.next(), "depth")?,
+                        "score" => info.score = parse_number(tokens.next(), "score")?,
+                        "nodes" => info.nodes = parse_number(tokens.next(), "nodes")?,
+                        "pv" => {
+                            // The principal variation runs to the end of the line
+                            for play in tokens.by_ref() {
+                                info.pv.push(parse_number(Some(play), "pv")?);
+                            }
+                        }
+                        _ => return Err(ProtocolError(format!("unexpected token: {}", token))),
+                    }
+                }
+                info.best_move = info.pv.first().copied();
+                Ok(Response::Info(info))
+            }
+            Some(response) => Err(ProtocolError(format!("unknown response: {}", response))),
+            None => Err(ProtocolError("empty response".to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Response {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Response::ReadyOk => write!(f, "readyok"),
+            Response::BestMove(Some(play)) => write!(f, "bestmove {}", play),
+            Response::BestMove(None) => write!(f, "bestmove none"),
+            Response::Info(info) => {
+                write!(
+                    f,
+                    "info depth {} score {} nodes {}",
+                    info.depth, info.score, info.nodes
+                )?;
+                if !info.pv.is_empty() {
+                    write!(f, " pv")?;
+                    for play in &info.pv {
+                        write!(f, " {}", play)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ROWS;
+
+    #[test]
+    fn test_parse_position() {
+        let expected = Command::Position(vec![4, 4, 5, 3]);
+        assert_eq!("position moves 4453".parse(), Ok(expected.clone()));
+        assert_eq!("position startpos moves 4453".parse(), Ok(expected));
+        assert_eq!("position startpos".parse(), Ok(Command::Position(vec![])));
+        assert!("position moves 4493".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn test_parse_go() {
+        let command: Command = "go depth 10 movetime 500".parse().unwrap();
+        let limits = SearchLimits {
+            depth: Some(10),
+            movetime: Some(Duration::from_millis(500)),
+        };
+        assert_eq!(command, Command::Go(limits));
+        assert_eq!(command.to_string(), "go depth 10 movetime 500");
+        assert_eq!("go".parse(), Ok(Command::Go(SearchLimits::default())));
+        assert!("go depth ten".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        let info = SearchInfo {
+            depth: 3,
+            score: -25,
+            best_move: Some(Play::drop(2)),
+            pv: vec![Play::drop(2), Play::pop(3), Play::drop(3)],
+            nodes: 120,
+        };
+        let line = Response::Info(info.clone()).to_string();
+        assert_eq!(line, "info depth 3 score -25 nodes 120 pv 2 p3 3");
+        assert_eq!(line.parse(), Ok(Response::Info(info)));
+
+        assert_eq!(
+            "bestmove 3".parse(),
+            Ok(Response::BestMove(Some(Play::drop(3))))
+        );
+        let line = Response::BestMove(Some(Play::pop(3))).to_string();
+        assert_eq!(line, "bestmove p3");
+        assert_eq!(line.parse(), Ok(Response::BestMove(Some(Play::pop(3)))));
+        assert_eq!("bestmove none".parse(), Ok(Response::BestMove(None)));
+    }
+
+    #[test]
+    fn test_board_from_moves() {
+        assert!(board_from_moves(&[3, 3, 2]).is_ok());
+        assert!(board_from_moves(&[0; ROWS + 1]).is_err());
+
+        // Player 1 wins on the seventh move, nothing may follow it
+        assert!(board_from_moves(&[0, 1, 0, 1, 0, 1, 0]).is_ok());
+        assert_eq!(
+            board_from_moves(&[0, 1, 0, 1, 0, 1, 0, 1]),
+            Err(ProtocolError("game is already over at ply 7".to_string()))
+        );
+    }
+}
@@ -0,0 +1,645 @@
+use crate::eval::EvalWeights;
+use crate::game::{Board, Cell, GameState, Move, MoveKind, Play, Position, PowerUp};
+use crate::player::PlayerId;
+use std::fmt;
+use std::sync::Arc;
+
+/// Captured discs needed to win a game of Pop Ten.
+pub const POP_TEN_TARGET: usize = 10;
+
+/**
+ * The rules a game is played by: which plays are legal, what a play does to
+ * the board, when the game is over, and how a position is scored. `Board`
+ * provides the pieces to build these from (dropping and popping discs,
+ * finding fours, passing the turn) and the AI searches with whatever
+ * ruleset it is given, so a variant can be tried out without touching
+ * either.
+ *
+ * The pieces that change the board are only open to this crate, so a board
+ * cannot be left half way through a play. Rules from elsewhere build on
+ * the ones here, e.g. restricting `is_legal` and keeping the default
+ * `apply`, and are given to a board with `Board::with_rules`.
+ *
+ * The variants that come with the game are picked with `Variant`, see
+ * `Variant::rules`.
+ */
+pub trait Ruleset: Send + Sync {
+    fn is_legal(&self, board: &Board, play: Play) -> bool;
+
+    /// Every legal play for the player to move, in the order the AI tries them.
+    fn legal_plays(&self, board: &Board) -> Vec<Play> {
+        let cols = board.get_size().cols;
+        let drops = (0..cols).map(Play::drop);
+        let pops = (0..cols).map(Play::pop);
+        drops
+            .chain(pops)
+            .filter(|&play| self.is_legal(board, play))
+            .collect()
+    }
+
+    /// Makes `play` for the player to move, or returns `None` if it is not legal.
+    fn apply(&self, board: &mut Board, play: Play) -> Option<Move> {
+        if !self.is_legal(board, play) {
+            return None;
+        }
+        let player = board.get_current_player().id;
+        let position = match play.kind {
+            MoveKind::Drop => board.drop_disc(play.col)?,
+            MoveKind::Pop => board.pop_disc(play.col)?,
+            MoveKind::Place(row) => board.place_disc(Position { row, col: play.col })?,
+            MoveKind::PowerUp(_) => return None,
+        };
+        board.pass_turn();
+        self.check_end(board);
+        Some(Move {
+            player,
+            position,
+            kind: play.kind,
+        })
+    }
+
+    /// Sets the game state once the game is won or drawn, after every play.
+    fn check_end(&self, board: &mut Board);
+
+    /// How much better the position is for `id` than for their opponents.
+    fn evaluate(&self, board: &Board, id: PlayerId, weights: &EvalWeights) -> i32 {
+        board.shape_score(id, weights)
+    }
+
+    /// Each player's points, for rulesets won on points rather than by a line alone.
+    fn scores(&self, _board: &Board) -> Option<Vec<usize>> {
+        None
+    }
+}
+
+/// A ruleset given to `Board::with_rules`. Boards are equal only if they share it.
+#[derive(Clone)]
+pub(crate) struct CustomRules(pub Arc<dyn Ruleset>);
+
+impl PartialEq for CustomRules {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for CustomRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CustomRules")
+    }
+}
+
+/// Drops only, the first to get four in a row wins and a full board is a draw.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Classic;
+
+impl Ruleset for Classic {
+    fn is_legal(&self, board: &Board, play: Play) -> bool {
+        play.kind == MoveKind::Drop && can_drop(board, play.col)
+    }
+
+    fn check_end(&self, board: &mut Board) {
+        if !four_wins(board) && board.is_full() {
+            board.set_game_state(GameState::Draw);
+        }
+    }
+}
+
+/// See `Variant::PopOut`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PopOut;
+
+impl Ruleset for PopOut {
+    fn is_legal(&self, board: &Board, play: Play) -> bool {
+        match play.kind {
+            MoveKind::Drop => can_drop(board, play.col),
+            MoveKind::Pop => can_pop(board, play.col),
+            MoveKind::PowerUp(_) | MoveKind::Place(_) => false,
+        }
+    }
+
+    fn check_end(&self, board: &mut Board) {
+        if four_wins(board) {
+            return;
+        }
+        if board.repeats_third_time() || (board.is_full() && self.legal_plays(board).is_empty()) {
+            board.set_game_state(GameState::Draw);
+        }
+    }
+}
+
+/// The scores and phase of a game of Pop Ten.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub(crate) struct PopTenState {
+    pub captured: [usize; 2],
+    /// The board has been filled and the players are popping.
+    pub filled: bool,
+    /// The player to move popped a disc they could not keep and must put it back.
+    pub holding: bool,
+}
+
+/// See `Variant::PopTen`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PopTen;
+
+impl Ruleset for PopTen {
+    fn is_legal(&self, board: &Board, play: Play) -> bool {
+        // Once the board is full you may only pop, or put back what you popped
+        let state = board.pop_ten();
+        let popping = state.filled && !state.holding;
+        match play.kind {
+            MoveKind::Drop => !popping && can_drop(board, play.col),
+            MoveKind::Pop => popping && can_pop(board, play.col),
+            MoveKind::PowerUp(_) | MoveKind::Place(_) => false,
+        }
+    }
+
+    fn apply(&self, board: &mut Board, play: Play) -> Option<Move> {
+        if !self.is_legal(board, play) {
+            return None;
+        }
+        let player = board.get_current_player().id;
+        let position = match play.kind {
+            MoveKind::Drop => {
+                let position = board.drop_disc(play.col)?;
+                board.pop_ten_mut().holding = false;
+                board.pass_turn();
+                position
+            }
+            MoveKind::Pop => {
+                let captures = board.in_four(
+                    Position {
+                        row: board.get_size().rows - 1,
+                        col: play.col,
+                    },
+                    player,
+                );
+                let position = board.pop_disc(play.col)?;
+                if captures {
+                    board.pop_ten_mut().captured[player as usize] += 1;
+                    board.pass_turn();
+                } else {
+                    // Same player again, to put the disc back
+                    board.pop_ten_mut().holding = true;
+                }
+                position
+            }
+            MoveKind::PowerUp(_) | MoveKind::Place(_) => return None,
+        };
+        self.check_end(board);
+        Some(Move {
+            player,
+            position,
+            kind: play.kind,
+        })
+    }
+
+    /// Fours do not win in Pop Ten, only captures do.
+    fn check_end(&self, board: &mut Board) {
+        if let Some(id) = [PlayerId::One, PlayerId::Two]
+            .into_iter()
+            .find(|&id| board.pop_ten().captured[id as usize] >= POP_TEN_TARGET)
+        {
+            board.set_game_state(GameState::Win(id));
+            return;
+        }
+
+        let full = board.is_full();
+        board.pop_ten_mut().filled |= full;
+        if !board.pop_ten().filled {
+            return;
+        }
+
+        if self.legal_plays(board).is_empty() {
+            // Nothing of theirs on the bottom row, so they miss their turn
+            board.pass_turn();
+            if self.legal_plays(board).is_empty() {
+                board.set_game_state(GameState::Draw);
+                return;
+            }
+        }
+
+        if board.repeats_third_time() {
+            board.set_game_state(GameState::Draw);
+        }
+    }
+
+    fn evaluate(&self, board: &Board, id: PlayerId, weights: &EvalWeights) -> i32 {
+        let captured = |id: PlayerId| board.pop_ten().captured[id as usize] as i32;
+        board.shape_score(id, weights) + (captured(id) - captured(id.opponent())) * weights.capture
+    }
+
+    fn scores(&self, board: &Board) -> Option<Vec<usize>> {
+        Some(board.pop_ten().captured.to_vec())
+    }
+}
+
+/// See `Variant::Elimination`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Elimination;
+
+impl Ruleset for Elimination {
+    fn is_legal(&self, board: &Board, play: Play) -> bool {
+        Classic.is_legal(board, play)
+    }
+
+    fn check_end(&self, board: &mut Board) {
+        let Some(last) = board.get_last_move() else {
+            return;
+        };
+        // Finished players' lines stay on the board, so only the mover's new one counts
+        if board.in_four(last.position, last.player) {
+            board.finish(last.player);
+        }
+        if board.active_players().len() > 1 && !board.is_full() {
+            return;
+        }
+        match board.finished().first() {
+            Some(&winner) => board.set_game_state(GameState::Win(winner)),
+            None => board.set_game_state(GameState::Draw),
+        }
+    }
+
+    /// A point for every player finishing behind you, by `PlayerId::index`.
+    fn scores(&self, board: &Board) -> Option<Vec<usize>> {
+        let players = board.get_players().len();
+        let mut scores = vec![0; players];
+        for (place, id) in board.finished().iter().enumerate() {
+            scores[id.index()] = players - 1 - place;
+        }
+        Some(scores)
+    }
+}
+
+/// The power-ups a player has left in `Variant::PowerUps`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Inventory {
+    /// By `PowerUp::ALL` order.
+    left: [u8; 4],
+}
+
+impl Inventory {
+    /// One of each.
+    pub fn starting() -> Self {
+        Inventory { left: [1; 4] }
+    }
+
+    pub fn has(self, power: PowerUp) -> bool {
+        self.left[power as usize] > 0
+    }
+
+    /// How many power-ups are left altogether.
+    pub fn count(self) -> usize {
+        self.left.iter().map(|&left| left as usize).sum()
+    }
+
+    fn take(&mut self, power: PowerUp) {
+        self.left[power as usize] -= 1;
+    }
+}
+
+impl fmt::Display for Inventory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let left: Vec<String> = PowerUp::ALL
+            .into_iter()
+            .filter(|&power| self.has(power))
+            .map(|power| power.to_string())
+            .collect();
+        if left.is_empty() {
+            write!(f, "nothing")
+        } else {
+            write!(f, "{}", left.join(", "))
+        }
+    }
+}
+
+/// See `Variant::PowerUps`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PowerUps;
+
+impl Ruleset for PowerUps {
+    fn is_legal(&self, board: &Board, play: Play) -> bool {
+        let id = board.get_current_player().id;
+        match play.kind {
+            MoveKind::Drop => can_drop(board, play.col),
+            MoveKind::Pop | MoveKind::Place(_) => false,
+            MoveKind::PowerUp(power) if !board.get_inventory(id).has(power) => false,
+            // Anything at the bottom can be blown up, even a wall
+            MoveKind::PowerUp(PowerUp::Bomb) => {
+                *board.get_game_state() == GameState::InProgress
+                    && play.col < board.get_size().cols
+                    && !matches!(
+                        board.get_board()[play.col][board.get_size().rows - 1],
+                        Cell::Empty | Cell::Blocked
+                    )
+            }
+            // The anvil crushes its way down, so even a full column takes it
+            MoveKind::PowerUp(PowerUp::Anvil) => {
+                *board.get_game_state() == GameState::InProgress
+                    && play.col < board.get_size().cols
+                    && board.get_board()[play.col][0] != Cell::Blocked
+            }
+            MoveKind::PowerUp(_) => can_drop(board, play.col),
+        }
+    }
+
+    /// Drops first, then each power-up the player still has in every column.
+    fn legal_plays(&self, board: &Board) -> Vec<Play> {
+        let cols = board.get_size().cols;
+        let drops = (0..cols).map(Play::drop);
+        let power_ups = PowerUp::ALL
+            .into_iter()
+            .flat_map(|power| (0..cols).map(move |col| Play::power_up(power, col)));
+        drops
+            .chain(power_ups)
+            .filter(|&play| self.is_legal(board, play))
+            .collect()
+    }
+
+    fn apply(&self, board: &mut Board, play: Play) -> Option<Move> {
+        if !self.is_legal(board, play) {
+            return None;
+        }
+        let player = board.get_current_player().id;
+        let position = match play.kind {
+            MoveKind::Drop => board.drop_disc(play.col)?,
+            MoveKind::Pop | MoveKind::Place(_) => return None,
+            MoveKind::PowerUp(power) => {
+                let position = match power {
+                    PowerUp::Anvil => board.drop_anvil(play.col)?,
+                    PowerUp::Bomb => board.bomb(play.col)?,
+                    PowerUp::Wall => board.drop_wall(play.col)?,
+                    PowerUp::PlayTwice => board.drop_disc(play.col)?,
+                };
+                board.inventory_mut(player).take(power);
+                position
+            }
+        };
+        if play.kind != MoveKind::PowerUp(PowerUp::PlayTwice) {
+            board.pass_turn();
+        }
+        self.check_end(board);
+        Some(Move {
+            player,
+            position,
+            kind: play.kind,
+        })
+    }
+
+    fn check_end(&self, board: &mut Board) {
+        // A bomb can hand the opponent a four as well
+        if !four_wins(board) && self.legal_plays(board).is_empty() {
+            board.set_game_state(GameState::Draw);
+        }
+    }
+
+    fn evaluate(&self, board: &Board, id: PlayerId, weights: &EvalWeights) -> i32 {
+        let left = |id: PlayerId| board.get_inventory(id).count() as i32;
+        let others: i32 = board
+            .get_players()
+            .iter()
+            .filter(|p| p.id != id)
+            .map(|p| left(p.id))
+            .sum();
+        board.shape_score(id, weights) + (left(id) - others) * weights.power_up
+    }
+}
+
+/// See `Variant::FreePlacement`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FreePlacement;
+
+impl Ruleset for FreePlacement {
+    fn is_legal(&self, board: &Board, play: Play) -> bool {
+        let MoveKind::Place(row) = play.kind else {
+            return false;
+        };
+        *board.get_game_state() == GameState::InProgress
+            && board
+                .get_board()
+                .get(play.col)
+                .and_then(|col| col.get(row))
+                .is_some_and(|&cell| cell == Cell::Empty)
+    }
+
+    /// Every empty cell, nearest the middle of the board first as those make the most lines.
+    fn legal_plays(&self, board: &Board) -> Vec<Play> {
+        let size = board.get_size();
+        let mut cells: Vec<Position> = (0..size.cols)
+            .flat_map(|col| (0..size.rows).map(move |row| Position { row, col }))
+            .collect();
+        cells.sort_by_key(|cell| {
+            (2 * cell.col).abs_diff(size.cols - 1) + (2 * cell.row).abs_diff(size.rows - 1)
+        });
+        cells
+            .into_iter()
+            .map(Play::place)
+            .filter(|&play| self.is_legal(board, play))
+            .collect()
+    }
+
+    fn check_end(&self, board: &mut Board) {
+        Classic.check_end(board);
+    }
+}
+
+/**
+ * Ends the game if anyone has a four. A pop can make fours for both players,
+ * in which case the one who just moved wins.
+ */
+fn four_wins(board: &mut Board) -> bool {
+    let mover = board.get_last_move().map(|last| last.player);
+    let others = board.get_players().iter().map(|p| p.id);
+    let ids: Vec<PlayerId> = mover.into_iter().chain(others).collect();
+    for id in ids {
+        if board.has_four(id) {
+            board.set_game_state(GameState::Win(id));
+            return true;
+        }
+    }
+    false
+}
+
+fn can_drop(board: &Board, col: usize) -> bool {
+    *board.get_game_state() == GameState::InProgress
+        && col < board.get_size().cols
+        && board.get_board()[col][0] == Cell::Empty
+}
+
+/// Whether the player to move has a disc at the bottom of `col`.
+fn can_pop(board: &Board, col: usize) -> bool {
+    let id = board.get_current_player().id;
+    *board.get_game_state() == GameState::InProgress
+        && col < board.get_size().cols
+        && matches!(board.get_board()[col][board.get_size().rows - 1],
+            Cell::Player(owner) if owner == id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::Agent;
+    use crate::ai::AI;
+    use crate::game::{Variant, COLS, ROWS};
+    use crate::player::PlayerType;
+    use crate::protocol::board_from_moves;
+
+    /// Classic rules, but only the even columns may be played.
+    struct EvenColumns;
+
+    impl Ruleset for EvenColumns {
+        fn is_legal(&self, board: &Board, play: Play) -> bool {
+            play.col.is_multiple_of(2) && Classic.is_legal(board, play)
+        }
+
+        fn check_end(&self, board: &mut Board) {
+            Classic.check_end(board);
+        }
+    }
+
+    #[test]
+    fn test_variant_rules() {
+        let board = board_from_moves(&[0, 0, 1, 2]).unwrap();
+        let popout = board.clone().with_variant(Variant::PopOut);
+
+        assert_eq!(Classic.legal_plays(&board), board.legal_plays());
+        assert_eq!(PopOut.legal_plays(&popout), popout.legal_plays());
+        assert_eq!(PopOut.legal_plays(&board).len(), COLS + 2);
+        assert!(!Classic.is_legal(&popout, Play::pop(0)));
+    }
+
+    #[test]
+    fn test_custom_ruleset() {
+        let mut board = Board::new_human_vs_human();
+        let ai = AI {
+            depth: 4,
+            ..AI::default()
+        };
+
+        assert_eq!(ai.analyse_with(&board, &Classic), ai.analyse(&board));
+        let even = ai.analyse_with(&board, &EvenColumns).best_move.unwrap();
+        assert!(even.col.is_multiple_of(2));
+
+        assert_eq!(EvenColumns.apply(&mut board, Play::drop(3)), None);
+        assert!(EvenColumns.apply(&mut board, even).is_some());
+
+        // Given to the board, the rules are used for play and search alike
+        let mut board = Board::new_human_vs_human().with_rules(EvenColumns);
+        assert!(!board.valid_play(Play::drop(3)));
+        assert_eq!(board.play(Play::drop(3)), None);
+        assert!(board
+            .legal_plays()
+            .iter()
+            .all(|play| play.col.is_multiple_of(2)));
+        assert_eq!(ai.analyse(&board), ai.analyse_with(&board, &EvenColumns));
+        assert!(board.clone().play(even).is_some());
+    }
+
+    #[test]
+    fn test_elimination() {
+        let mut board =
+            Board::new_multiplayer(&vec![PlayerType::Human; 3]).with_variant(Variant::Elimination);
+        let play = |board: &mut Board, cols: &[usize]| {
+            for &col in cols {
+                assert!(board.play(Play::drop(col)).is_some());
+            }
+        };
+
+        // Player 1 makes a line along the bottom and finishes first
+        play(&mut board, &[0, 6, 6, 1, 6, 6, 2, 6, 6, 3]);
+        assert_eq!(board.get_game_state(), &GameState::InProgress);
+        assert_eq!(board.finished(), [PlayerId::One]);
+        assert_eq!(board.active_players(), [PlayerId::Two, PlayerId::Three]);
+        assert_eq!(board.get_current_player().id, PlayerId::Two);
+
+        // Player 2 is second with a line up column 5, which ends it for Player 3
+        play(&mut board, &[5, 4, 5, 4, 5, 4, 5]);
+        assert_eq!(board.get_game_state(), &GameState::Win(PlayerId::One));
+        assert_eq!(board.finished(), [PlayerId::One, PlayerId::Two]);
+        assert_eq!(board.scores(), Some(vec![2, 1, 0]));
+    }
+
+    #[test]
+    #[should_panic(expected = "PopTen is for two players")]
+    fn test_pop_ten_needs_two_players() {
+        Board::new_multiplayer(&vec![PlayerType::Human; 3]).with_variant(Variant::PopTen);
+    }
+
+    #[test]
+    fn test_power_ups() {
+        let mut board = Board::new_human_vs_human().with_variant(Variant::PowerUps);
+        let one = |board: &Board| board.get_inventory(PlayerId::One);
+        assert_eq!(one(&board), Inventory::starting());
+        assert_eq!(PowerUps.legal_plays(&board).len(), COLS * 4);
+
+        // Play twice keeps the turn, and is then used up
+        assert!(board.play(Play::power_up(PowerUp::PlayTwice, 0)).is_some());
+        assert_eq!(board.get_current_player().id, PlayerId::One);
+        assert!(!one(&board).has(PowerUp::PlayTwice));
+        assert!(!board.valid_play(Play::power_up(PowerUp::PlayTwice, 1)));
+
+        // X X X and a wall on the bottom row is no four
+        for play in [Play::drop(1), Play::drop(6), Play::drop(2), Play::drop(6)] {
+            assert!(board.play(play).is_some());
+        }
+        assert!(board.play(Play::power_up(PowerUp::Wall, 3)).is_some());
+        assert_eq!(board.get_board()[3][ROWS - 1], Cell::Wall);
+        assert_eq!(board.get_game_state(), &GameState::InProgress);
+
+        // O blows the wall up, and the disc above it falls into the gap
+        for play in [
+            Play::drop(3),
+            Play::drop(5),
+            Play::power_up(PowerUp::Bomb, 3),
+        ] {
+            assert!(board.play(play).is_some());
+        }
+        assert!(matches!(
+            board.get_board()[3][ROWS - 1],
+            Cell::Player(owner) if owner == PlayerId::Two
+        ));
+        assert_eq!(one(&board).to_string(), "anvil, bomb");
+    }
+
+    #[test]
+    fn test_anvil_on_full_column() {
+        let mut board = Board::new_human_vs_human().with_variant(Variant::PowerUps);
+        for _ in 0..ROWS {
+            assert!(board.play_move(0).is_some());
+        }
+        assert!(!board.valid_play(Play::drop(0)));
+        assert!(!board.valid_play(Play::power_up(PowerUp::Wall, 0)));
+        assert!(board.valid_play(Play::power_up(PowerUp::Anvil, 0)));
+
+        // Only the anvil is left in the column
+        assert!(board.play(Play::power_up(PowerUp::Anvil, 0)).is_some());
+        let col = &board.get_board()[0];
+        assert!(col[..ROWS - 1].iter().all(|&cell| cell == Cell::Empty));
+        assert!(matches!(col[ROWS - 1], Cell::Player(owner) if owner == PlayerId::One));
+        assert!(!board.get_inventory(PlayerId::One).has(PowerUp::Anvil));
+    }
+
+    #[test]
+    fn test_ai_drops_anvil_to_win() {
+        // X has 0, 1 and 2 along the bottom, but O sits at the bottom of column 3
+        let mut board = Board::new_human_vs_human().with_variant(Variant::PowerUps);
+        for col in [0, 3, 1, 6, 2, 6] {
+            assert!(board.play_move(col).is_some());
+        }
+        let ai = AI {
+            depth: 2,
+            ..AI::default()
+        };
+
+        assert_eq!(
+            ai.best_play(&board),
+            Some(Play::power_up(PowerUp::Anvil, 3))
+        );
+        let mut agent = ai;
+        assert_eq!(
+            Agent::choose_move(&mut agent, &board, &[]),
+            Ok(Play::power_up(PowerUp::Anvil, 3))
+        );
+        board.play(Play::power_up(PowerUp::Anvil, 3));
+        assert_eq!(board.get_game_state(), &GameState::Win(PlayerId::One));
+    }
+}
@@ -0,0 +1,186 @@
+use crate::game::{Board, FirstPlayer, GameConfig, GameRecord, GameState};
+use crate::player::{PlayerId, PlayerType};
+use crate::spectator::Broadcaster;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// When a `Match` is over.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MatchLength {
+    /// At most this many games, stopping early once the winner can no longer be caught.
+    BestOf(usize),
+    /// Until someone has won `wins` games, or `max_games` have been played.
+    FirstTo { wins: usize, max_games: usize },
+}
+
+/// The running score of a `Match`.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct MatchScore {
+    /// Games won by Player 1 and Player 2.
+    pub wins: [usize; 2],
+    pub draws: usize,
+    pub records: Vec<GameRecord>,
+}
+
+impl MatchScore {
+    pub fn games(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Whoever has won more games, if anyone.
+    pub fn leader(&self) -> Option<PlayerId> {
+        match self.wins[0].cmp(&self.wins[1]) {
+            Ordering::Greater => Some(PlayerId::One),
+            Ordering::Less => Some(PlayerId::Two),
+            Ordering::Equal => None,
+        }
+    }
+
+    pub fn is_over(&self, length: MatchLength) -> bool {
+        match length {
+            MatchLength::BestOf(games) => {
+                let left = games.saturating_sub(self.games());
+                self.wins[0].abs_diff(self.wins[1]) > left || left == 0
+            }
+            MatchLength::FirstTo { wins, max_games } => {
+                self.wins.iter().any(|&won| won >= wins) || self.games() >= max_games
+            }
+        }
+    }
+
+    fn add(&mut self, record: GameRecord) {
+        match record.state {
+            // Matches are between two players
+            GameState::Win(id) => self.wins[id.index()] += 1,
+            _ => self.draws += 1,
+        }
+        self.records.push(record);
+    }
+}
+
+impl fmt::Display for MatchScore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Player 1 {} - {} Player 2 with {} draw{} after {} game{}",
+            self.wins[0],
+            self.wins[1],
+            self.draws,
+            if self.draws == 1 { "" } else { "s" },
+            self.games(),
+            if self.games() == 1 { "" } else { "s" },
+        )
+    }
+}
+
+/**
+ * A series of games between the same two players, human or AI, taking turns
+ * to start. Each game gets its own seed, drawn from the match seed, and is
+ * kept in the score's records so it can be replayed.
+ */
+pub struct Match {
+    players: [PlayerType; 2],
+    length: MatchLength,
+    seed: u64,
+}
+
+impl Match {
+    pub fn new(one: PlayerType, two: PlayerType, length: MatchLength) -> Self {
+        Match {
+            players: [one, two],
+            length,
+            seed: rand::random(),
+        }
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Plays the match. `on_game` sees the score after every game.
+    pub fn play(
+        &self,
+        spectators: &Broadcaster,
+        mut on_game: impl FnMut(&MatchScore),
+    ) -> MatchScore {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut score = MatchScore::default();
+
+        while !score.is_over(self.length) {
+            let config = GameConfig {
+                seed: rng.gen(),
+                first_player: FirstPlayer::Alternate,
+                game_number: score.games(),
+            };
+            let mut board = Board::new(self.players[0].clone(), self.players[1].clone());
+            score.add(board.play_game_with_config(config, spectators));
+            on_game(&score);
+        }
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::AI;
+
+    fn record(state: GameState) -> GameRecord {
+        GameRecord {
+            seed: 0,
+            first_player: PlayerId::One,
+            moves: Vec::new(),
+            state,
+            scores: None,
+        }
+    }
+
+    #[test]
+    fn test_match_lengths() {
+        let win = |id| GameState::Win(id);
+
+        let mut score = MatchScore::default();
+        score.add(record(win(PlayerId::One)));
+        score.add(record(win(PlayerId::One)));
+        assert!(!score.is_over(MatchLength::BestOf(5)));
+        score.add(record(win(PlayerId::One)));
+        // 3-0 in a best of 5 cannot be caught
+        assert!(score.is_over(MatchLength::BestOf(5)));
+
+        let mut score = MatchScore::default();
+        score.add(record(GameState::Draw));
+        score.add(record(win(PlayerId::Two)));
+        let first_to_two = MatchLength::FirstTo {
+            wins: 2,
+            max_games: 10,
+        };
+        assert!(!score.is_over(first_to_two));
+        score.add(record(win(PlayerId::Two)));
+        assert!(score.is_over(first_to_two));
+        assert_eq!(score.leader(), Some(PlayerId::Two));
+        assert_eq!(
+            score.to_string(),
+            "Player 1 0 - 2 Player 2 with 1 draw after 3 games"
+        );
+    }
+
+    #[test]
+    fn test_match_alternates_starts() {
+        let ai = PlayerType::AI(AI {
+            depth: 2,
+            ..AI::default()
+        });
+        let mut seen = Vec::new();
+        let score = Match::new(ai.clone(), ai, MatchLength::BestOf(4))
+            .seed(11)
+            .play(&Broadcaster::new(), |score| seen.push(score.games()));
+
+        assert_eq!(score.games(), score.wins[0] + score.wins[1] + score.draws);
+        assert_eq!(seen, (1..=score.games()).collect::<Vec<_>>());
+        let starters: Vec<PlayerId> = score.records.iter().map(|r| r.first_player).collect();
+        assert_eq!(starters[..2], [PlayerId::One, PlayerId::Two]);
+    }
+}
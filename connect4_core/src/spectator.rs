@@ -0,0 +1,250 @@
+use crate::game::{Board, GameState, Move};
+use std::io::Write;
+use std::net::TcpListener;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::{fmt, thread};
+
+/// Everything a spectator can see of a running game.
+#[derive(Clone, PartialEq, Debug)]
+pub enum GameEvent {
+    /// Sent first to every subscriber so late joiners can catch up.
+    Snapshot {
+        board: Board,
+        moves: Vec<Move>,
+    },
+    /// A move was played. The evaluation is from Player 1's point of view.
+    Move {
+        player_move: Move,
+        board: Board,
+        evaluation: i32,
+    },
+    GameOver(GameState),
+}
+
+impl fmt::Display for GameEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameEvent::Snapshot { board, moves } => {
+                writeln!(f, "Joined after {} moves", moves.len())?;
+                write!(f, "{}", board)
+            }
+            GameEvent::Move {
+                player_move,
+                board,
+                evaluation,
+            } => {
+                writeln!(f, "{}", player_move)?;
+                writeln!(f, "Evaluation: {}", evaluation)?;
+                write!(f, "{}", board)
+            }
+            GameEvent::GameOver(state) => write!(f, "{}", state),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    subscribers: Vec<Sender<GameEvent>>,
+    board: Option<Board>,
+    moves: Vec<Move>,
+    result: Option<GameState>,
+}
+
+/**
+ * Fans game events out to any number of spectators. Cloning a Broadcaster
+ * shares the same game, so spectators can subscribe from other threads while
+ * the game is being played.
+ */
+#[derive(Clone, Default)]
+pub struct Broadcaster {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Broadcaster {
+    pub fn new() -> Self {
+        Broadcaster::default()
+    }
+
+    /// Subscribe to the game. The first event is always a `Snapshot` of the
+    /// game so far, followed by `GameOver` if the game has already ended.
+    pub fn subscribe(&self) -> Receiver<GameEvent> {
+        let (sender, receiver) = channel();
+        let mut inner = self.inner.lock().unwrap();
+
+        if let Some(board) = &inner.board {
+            let _ = sender.send(GameEvent::Snapshot {
+                board: board.clone(),
+                moves: inner.moves.clone(),
+            });
+        }
+        if let Some(state) = inner.result {
+            let _ = sender.send(GameEvent::GameOver(state));
+        }
+
+        inner.subscribers.push(sender);
+        receiver
+    }
+
+    pub fn spectator_count(&self) -> usize {
+        self.inner.lock().unwrap().subscribers.len()
+    }
+
+    /// Called once before the first move.
+    pub fn start(&self, board: &Board) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.board = Some(board.clone());
+        inner.moves.clear();
+        inner.result = None;
+
+        let event = GameEvent::Snapshot {
+            board: board.clone(),
+            moves: Vec::new(),
+        };
+        Self::send(&mut inner, event);
+    }
+
+    pub fn publish_move(&self, player_move: Move, board: &Board, evaluation: i32) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.board = Some(board.clone());
+        inner.moves.push(player_move);
+
+        let event = GameEvent::Move {
+            player_move,
+            board: board.clone(),
+            evaluation,
+        };
+        Self::send(&mut inner, event);
+    }
+
+    pub fn finish(&self, state: GameState) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.result = Some(state);
+        Self::send(&mut inner, GameEvent::GameOver(state));
+    }
+
+    /// Spectators that hung up are dropped here.
+    fn send(inner: &mut Inner, event: GameEvent) {
+        inner
+            .subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    /**
+     * Serve the game over TCP. Every connection is subscribed and receives the
+     * events as text, e.g. `nc localhost 4000` to watch a game.
+     */
+    pub fn serve(&self, listener: TcpListener) -> thread::JoinHandle<()> {
+        let broadcaster = self.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let events = broadcaster.subscribe();
+                thread::spawn(move || {
+                    for event in events {
+                        if writeln!(stream, "{}", event).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpStream;
+
+    fn play(board: &mut Board, broadcaster: &Broadcaster, col: usize) {
+        let player_move = board.play_move(col).unwrap();
+        broadcaster.publish_move(player_move, board, 0);
+    }
+
+    #[test]
+    fn test_subscriber_receives_moves_and_result() {
+        let mut board = Board::new_human_vs_human();
+        let broadcaster = Broadcaster::new();
+        let events = broadcaster.subscribe();
+
+        broadcaster.start(&board);
+        play(&mut board, &broadcaster, 3);
+        broadcaster.finish(GameState::Draw);
+
+        assert!(matches!(events.recv().unwrap(), GameEvent::Snapshot { .. }));
+        match events.recv().unwrap() {
+            GameEvent::Move { player_move, .. } => assert_eq!(player_move.position.col, 3),
+            event => panic!("unexpected event {:?}", event),
+        }
+        assert_eq!(events.recv().unwrap(), GameEvent::GameOver(GameState::Draw));
+    }
+
+    #[test]
+    fn test_late_joiner_gets_snapshot() {
+        let mut board = Board::new_human_vs_human();
+        let broadcaster = Broadcaster::new();
+
+        broadcaster.start(&board);
+        play(&mut board, &broadcaster, 0);
+        play(&mut board, &broadcaster, 1);
+
+        let events = broadcaster.subscribe();
+        match events.recv().unwrap() {
+            GameEvent::Snapshot {
+                board: snapshot,
+                moves,
+            } => {
+                assert_eq!(snapshot, board);
+                assert_eq!(moves.len(), 2);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+
+        broadcaster.finish(GameState::Draw);
+        let events = broadcaster.subscribe();
+        assert!(matches!(events.recv().unwrap(), GameEvent::Snapshot { .. }));
+        assert_eq!(events.recv().unwrap(), GameEvent::GameOver(GameState::Draw));
+    }
+
+    #[test]
+    fn test_disconnected_spectators_are_dropped() {
+        let board = Board::new_human_vs_human();
+        let broadcaster = Broadcaster::new();
+
+        let events = broadcaster.subscribe();
+        drop(broadcaster.subscribe());
+        assert_eq!(broadcaster.spectator_count(), 2);
+
+        broadcaster.start(&board);
+        assert_eq!(broadcaster.spectator_count(), 1);
+        drop(events);
+    }
+
+    #[test]
+    fn test_serve_over_tcp() {
+        let board = Board::new_human_vs_human();
+        let broadcaster = Broadcaster::new();
+        broadcaster.start(&board);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        broadcaster.serve(listener);
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        while broadcaster.spectator_count() == 0 {
+            thread::yield_now();
+        }
+        broadcaster.finish(GameState::Draw);
+
+        let mut text = String::new();
+        let mut buf = [0; 512];
+        while !text.contains("Draw") {
+            let n = stream.read(&mut buf).unwrap();
+            assert!(n > 0);
+            text.push_str(&String::from_utf8_lossy(&buf[..n]));
+        }
+        assert!(text.starts_with("Joined after 0 moves"));
+    }
+}
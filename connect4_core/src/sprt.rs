@@ -0,0 +1,389 @@
+use crate::ai::AI;
+use crate::game::{GameState, COLS};
+use crate::protocol::board_from_moves;
+use crate::tournament::{game_seed, play_pairing, Entrant, Record};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/**
+ * Sequential probability ratio test between H0: the candidate is `elo0`
+ * stronger than the baseline, and H1: it is `elo1` stronger. `alpha` and
+ * `beta` are the false positive and false negative rates.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SprtConfig {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+    /// Give up without a decision after this many games.
+    pub max_games: usize,
+}
+
+impl Default for SprtConfig {
+    fn default() -> Self {
+        SprtConfig {
+            elo0: 0.0,
+            elo1: 50.0,
+            alpha: 0.05,
+            beta: 0.05,
+            max_games: 2000,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Decision {
+    /// The candidate is no better than `elo0`.
+    AcceptH0,
+    /// The candidate is better by around `elo1`.
+    AcceptH1,
+    Continue,
+}
+
+impl SprtConfig {
+    /// Bounds on the log-likelihood ratio for accepting H0 and H1.
+    pub fn bounds(&self) -> (f64, f64) {
+        let lower = (self.beta / (1.0 - self.alpha)).ln();
+        let upper = ((1.0 - self.beta) / self.alpha).ln();
+        (lower, upper)
+    }
+
+    /**
+     * Log-likelihood ratio of H1 over H0 for the candidate's results, using
+     * the usual normal approximation for win/draw/loss outcomes.
+     */
+    pub fn llr(&self, record: &Record) -> f64 {
+        if record.games() == 0 {
+            return 0.0;
+        }
+
+        // An outcome that never happened is counted as half a game, otherwise
+        // a few straight wins would have no variance and decide the test alone
+        let regularize = |count: u32| if count == 0 { 0.5 } else { count as f64 };
+        let (wins, draws, losses) = (
+            regularize(record.wins),
+            regularize(record.draws),
+            regularize(record.losses),
+        );
+        let total = wins + draws + losses;
+        let score = (wins + draws / 2.0) / total;
+        let variance =
+            (wins * (1.0 - score).powi(2) + draws * (0.5 - score).powi(2) + losses * score.powi(2))
+                / total;
+
+        let score0 = elo_to_score(self.elo0);
+        let score1 = elo_to_score(self.elo1);
+        record.games() as f64 * (score1 - score0) * (2.0 * score - score0 - score1)
+            / (2.0 * variance)
+    }
+
+    pub fn decide(&self, record: &Record) -> Decision {
+        let llr = self.llr(record);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            Decision::AcceptH1
+        } else if llr <= lower {
+            Decision::AcceptH0
+        } else {
+            Decision::Continue
+        }
+    }
+}
+
+/// The expected score against an opponent `elo` points weaker.
+pub fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// Where a test stands, from the candidate's point of view.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SprtStatus {
+    pub record: Record,
+    pub llr: f64,
+    pub bounds: (f64, f64),
+    pub decision: Decision,
+}
+
+impl fmt::Display for SprtStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Games: {} (W-D-L {})  LLR: {:.2} [{:.2}, {:.2}]",
+            self.record.games(),
+            self.record,
+            self.llr,
+            self.bounds.0,
+            self.bounds.1
+        )?;
+        match self.decision {
+            Decision::AcceptH0 => write!(f, "  H0 accepted"),
+            Decision::AcceptH1 => write!(f, "  H1 accepted"),
+            Decision::Continue => Ok(()),
+        }
+    }
+}
+
+/**
+ * Openings of `plies` moves that `ai` scores within `margin` of equal for the
+ * player to move, so neither side starts out winning.
+ */
+pub fn balanced_openings(plies: usize, ai: AI, margin: i32) -> Vec<Vec<usize>> {
+    let mut openings = vec![Vec::new()];
+    for _ in 0..plies {
+        openings = openings
+            .iter()
+            .flat_map(|opening| {
+                (0..COLS).map(move |col| {
+                    let mut next = opening.clone();
+                    next.push(col);
+                    next
+                })
+            })
+            .filter(|opening| board_from_moves(opening).is_ok())
+            .collect();
+    }
+
+    openings
+        .into_iter()
+        .filter(|opening| {
+            let board = board_from_moves(opening).unwrap();
+            let stop = AtomicBool::new(false);
+            let info = ai.search(&board, Default::default(), &stop, |_| {});
+            info.score.abs() <= margin
+        })
+        .collect()
+}
+
+pub struct Sprt {
+    config: SprtConfig,
+    openings: Vec<Vec<usize>>,
+    random_plies: usize,
+    seed: u64,
+    threads: usize,
+}
+
+impl Sprt {
+    pub fn new(config: SprtConfig) -> Self {
+        Sprt {
+            config,
+            openings: vec![Vec::new()],
+            random_plies: 2,
+            seed: rand::random(),
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+
+    /**
+     * Each pair of games starts from one of `openings`, picked at random and
+     * played twice with colors swapped.
+     */
+    pub fn openings(mut self, openings: Vec<Vec<usize>>) -> Self {
+        if !openings.is_empty() {
+            self.openings = openings;
+        }
+        self
+    }
+
+    /**
+     * Random moves added to the end of each opening. Deterministic AIs play
+     * the same game from the same start, so without these a test would
+     * just repeat a handful of games.
+     */
+    pub fn random_plies(mut self, plies: usize) -> Self {
+        self.random_plies = plies;
+        self
+    }
+
+    /**
+     * Where the openings, random plies and the agents' own random choices
+     * are drawn from, so a test can be run again.
+     */
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// The start of the `pair`th pair of games.
+    fn opening(&self, pair: usize) -> Vec<usize> {
+        let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(pair as u64));
+        let mut opening = self.openings.choose(&mut rng).unwrap().clone();
+        for _ in 0..self.random_plies {
+            let board = board_from_moves(&opening).unwrap();
+            // Nothing that ends the game before it has started
+            let cols: Vec<usize> = (0..COLS)
+                .filter(|&col| {
+                    let mut next = board.clone();
+                    next.play_move(col).is_some() && *next.get_game_state() == GameState::InProgress
+                })
+                .collect();
+            match cols.choose(&mut rng) {
+                Some(&col) => opening.push(col),
+                None => break,
+            }
+        }
+        opening
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    fn status(&self, record: Record) -> SprtStatus {
+        SprtStatus {
+            record,
+            llr: self.config.llr(&record),
+            bounds: self.config.bounds(),
+            decision: self.config.decide(&record),
+        }
+    }
+
+    /**
+     * Plays `candidate` against `baseline` until the test reaches a decision or
+     * `max_games` is hit. `on_game` sees the status after every finished game.
+     * Games still running when a decision is made are waited for but not counted.
+     */
+    pub fn run(
+        &self,
+        candidate: &Entrant,
+        baseline: &Entrant,
+        on_game: impl FnMut(&SprtStatus) + Send,
+    ) -> SprtStatus {
+        let next_game = AtomicUsize::new(0);
+        let done = AtomicBool::new(false);
+        let state = Mutex::new((Record::default(), on_game));
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads {
+                scope.spawn(|| {
+                    while !done.load(Ordering::Relaxed) {
+                        let game = next_game.fetch_add(1, Ordering::Relaxed);
+                        if game >= self.config.max_games {
+                            break;
+                        }
+
+                        let opening = self.opening(game / 2);
+                        // The candidate is entrant 0 and the baseline 1, whichever color they play
+                        let seed = |entrant| game_seed(self.seed, game, entrant);
+                        let record = if game.is_multiple_of(2) {
+                            play_pairing(candidate, baseline, &opening, [seed(0), seed(1)])
+                        } else {
+                            play_pairing(baseline, candidate, &opening, [seed(1), seed(0)])
+                                .reversed()
+                        };
+
+                        let mut state = state.lock().unwrap();
+                        if done.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        state.0.add(record);
+                        let status = self.status(state.0);
+                        (state.1)(&status);
+                        if status.decision != Decision::Continue {
+                            done.store(true, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+
+        let (record, _) = state.into_inner().unwrap();
+        self.status(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(wins: u32, draws: u32, losses: u32) -> Record {
+        Record {
+            wins,
+            draws,
+            losses,
+        }
+    }
+
+    #[test]
+    fn test_llr() {
+        let config = SprtConfig::default();
+
+        assert_eq!(config.llr(&record(0, 0, 0)), 0.0);
+        assert!(config.llr(&record(30, 10, 10)) > 0.0);
+        assert!(config.llr(&record(10, 10, 30)) < 0.0);
+        // An even score is closer to H0 (+0 Elo) than to H1 (+50 Elo)
+        assert!(config.llr(&record(500, 0, 500)) < 0.0);
+    }
+
+    #[test]
+    fn test_decisions() {
+        let config = SprtConfig::default();
+
+        assert_eq!(config.decide(&record(3, 0, 2)), Decision::Continue);
+        assert_eq!(config.decide(&record(300, 100, 100)), Decision::AcceptH1);
+        assert_eq!(config.decide(&record(100, 100, 300)), Decision::AcceptH0);
+    }
+
+    #[test]
+    fn test_balanced_openings() {
+        let openings = balanced_openings(
+            1,
+            AI {
+                depth: 1,
+                ..AI::default()
+            },
+            0,
+        );
+
+        assert!(!openings.is_empty());
+        assert!(openings.iter().all(|opening| opening.len() == 1));
+    }
+
+    #[test]
+    fn test_openings_vary() {
+        let sprt = Sprt::new(SprtConfig::default())
+            .openings(vec![vec![3]])
+            .seed(1);
+
+        let openings: Vec<Vec<usize>> = (0..10).map(|pair| sprt.opening(pair)).collect();
+        assert!(openings
+            .iter()
+            .all(|opening| opening.len() == 3 && opening[0] == 3));
+        assert!(openings.iter().any(|opening| *opening != openings[0]));
+        assert_eq!(sprt.opening(4), openings[4]);
+    }
+
+    #[test]
+    fn test_stronger_candidate_passes() {
+        let config = SprtConfig {
+            elo0: 0.0,
+            elo1: 200.0,
+            max_games: 200,
+            ..SprtConfig::default()
+        };
+        let openings = (0..COLS).map(|col| vec![col]).collect();
+
+        let mut games = 0;
+        let status = Sprt::new(config).openings(openings).threads(4).run(
+            &Entrant::ai(AI {
+                depth: 4,
+                ..AI::default()
+            }),
+            &Entrant::ai(AI {
+                depth: 1,
+                ..AI::default()
+            }),
+            |_| games += 1,
+        );
+
+        assert_eq!(status.decision, Decision::AcceptH1);
+        assert_eq!(status.record.games(), games);
+        assert!(status.llr >= status.bounds.1);
+    }
+}
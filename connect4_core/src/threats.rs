@@ -0,0 +1,276 @@
+use crate::eval::EvalWeights;
+use crate::game::{Board, Cell, Position};
+use crate::player::PlayerId;
+
+/**
+ * An empty cell that would complete a four for `player`. Rows are counted
+ * from the bottom starting at 1, as is usual when talking about Connect 4
+ * strategy: with correct play the player who gets the odd cells can usually
+ * cash in a threat on an odd row and the other player one on an even row,
+ * because of who is forced to fill the cell beneath it (zugzwang). See
+ * `odd_player` for who that is.
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Threat {
+    pub position: Position,
+    pub player: PlayerId,
+}
+
+impl Threat {
+    /// 1 for the bottom row up to the number of rows for the top one.
+    pub fn row_from_bottom(&self, board: &Board) -> usize {
+        board.get_size().rows - self.position.row
+    }
+
+    /**
+     * Whether the row is odd counting up from the floor of its column: the
+     * bottom, or the highest blocked cell beneath the threat, since the cells
+     * are filled in pairs from there.
+     */
+    pub fn is_odd(&self, board: &Board) -> bool {
+        let Position { row, col } = self.position;
+        let rows = board.get_size().rows;
+        let column = &board.get_board()[col];
+        let floor = (row + 1..rows)
+            .find(|&below| column[below] == Cell::Blocked)
+            .unwrap_or(rows);
+        (floor - row) % 2 == 1
+    }
+
+    /**
+     * Odd threats are good for the player who gets the odd cells and even
+     * threats for the other. With more players the turns do not fall that
+     * neatly, so neither is good for anyone.
+     */
+    pub fn has_good_parity(&self, board: &Board) -> bool {
+        match odd_player(board) {
+            Some(odd) => (self.player == odd) == self.is_odd(board),
+            None => false,
+        }
+    }
+
+    /// Whether the threat can be played right now, rather than being stacked on empty cells.
+    pub fn is_playable(&self, board: &Board) -> bool {
+        let Position { row, col } = self.position;
+        !board.get_variant().has_gravity()
+            || row + 1 == board.get_size().rows
+            || board.get_board()[col][row + 1] != Cell::Empty
+    }
+}
+
+/**
+ * Who gets the odd cells if both players keep filling the board: whoever is
+ * to move with an even number of cells left, as Player 1 is at the start of
+ * a standard game. Which player started, a layout's `to-move` and its blocked
+ * cells all change who that is. `None` with more than two players.
+ */
+pub fn odd_player(board: &Board) -> Option<PlayerId> {
+    let [one, two] = board.get_players() else {
+        return None;
+    };
+    let to_move = board.get_current_player().id;
+    let other = if to_move == one.id { two.id } else { one.id };
+    match board.cells_left() % 2 {
+        0 => Some(to_move),
+        _ => Some(other),
+    }
+}
+
+/// Whether a disc of `player` at `position` would make a four in any direction.
+fn completes_four(board: &Board, position: Position, player: PlayerId) -> bool {
+    board.windows().iter().any(|window| {
+        window.contains(&position)
+            && window.iter().all(|&cell| {
+                cell == position
+                    || matches!(board.get_board()[cell.col][cell.row],
+                        Cell::Player(owner) if owner == player)
+            })
+    })
+}
+
+/// Every threat on the board, for every player. A cell can be a threat for several.
+pub fn find_threats(board: &Board) -> Vec<Threat> {
+    let mut threats = Vec::new();
+    let size = board.get_size();
+
+    for col in 0..size.cols {
+        for row in 0..size.rows {
+            if board.get_board()[col][row] != Cell::Empty {
+                continue;
+            }
+            let position = Position { row, col };
+            for player in board.get_players().iter().map(|p| p.id) {
+                if completes_four(board, position, player) {
+                    threats.push(Threat { position, player });
+                }
+            }
+        }
+    }
+
+    threats
+}
+
+/**
+ * The threat part of the evaluation for `player`: their threats count for
+ * them and everyone else's against, weighted by whether they are playable now
+ * and, if not, whether their parity suits their owner.
+ */
+pub fn threat_score(board: &Board, player: PlayerId, weights: &EvalWeights) -> i32 {
+    find_threats(board)
+        .iter()
+        .map(|threat| {
+            let value = if threat.is_playable(board) {
+                weights.playable_threat
+            } else if threat.has_good_parity(board) {
+                weights.good_threat
+            } else {
+                weights.bad_threat
+            };
+            if threat.player == player {
+                value
+            } else {
+                -value
+            }
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::EvalMode;
+    use crate::game::{Variant, ROWS};
+    use crate::protocol::board_from_moves;
+
+    /*
+     * _ _ _ _ _ _ _
+     * _ _ _ _ _ _ _
+     * _ _ _ _ _ _ _
+     * _ _ _ _ _ _ _
+     * X X X _ _ _ _
+     * O O O _ _ _ X
+     */
+    fn stacked_position() -> Board {
+        board_from_moves(&[6, 0, 0, 1, 1, 2, 2]).unwrap()
+    }
+
+    #[test]
+    fn test_find_threats() {
+        let board = stacked_position();
+        let threats = find_threats(&board);
+
+        // Column 3 completes both rows
+        assert_eq!(threats.len(), 2);
+
+        let x = threats.iter().find(|t| t.player == PlayerId::One).unwrap();
+        assert_eq!(
+            x.position,
+            Position {
+                row: ROWS - 2,
+                col: 3
+            }
+        );
+        assert_eq!(x.row_from_bottom(&board), 2);
+        assert!(!x.is_playable(&board));
+        assert!(!x.has_good_parity(&board));
+
+        let o = threats.iter().find(|t| t.player == PlayerId::Two).unwrap();
+        assert_eq!(o.row_from_bottom(&board), 1);
+        assert!(o.is_playable(&board));
+        assert!(!o.has_good_parity(&board));
+    }
+
+    #[test]
+    fn test_parity_follows_first_player() {
+        let board = stacked_position();
+        assert_eq!(odd_player(&board), Some(PlayerId::One));
+
+        // The mirror image with Player 2 having started: O O O stacked on X X X
+        let empty = "_ _ _ _ _ _ _\n".repeat(ROWS);
+        let layout = format!("to-move O\n{}", empty).parse().unwrap();
+        let mut swapped = Board::new_human_vs_human().with_layout(&layout).unwrap();
+        for col in [6, 0, 0, 1, 1, 2, 2] {
+            swapped.play_move(col).unwrap();
+        }
+        assert_eq!(odd_player(&swapped), Some(PlayerId::Two));
+        let threats = find_threats(&swapped);
+        let o = threats.iter().find(|t| t.player == PlayerId::Two).unwrap();
+        let x = threats.iter().find(|t| t.player == PlayerId::One).unwrap();
+        // Player 2's even threat and Player 1's odd one both suit the other side
+        assert!(!o.is_odd(&swapped) && !o.has_good_parity(&swapped));
+        assert!(x.is_odd(&swapped) && !x.has_good_parity(&swapped));
+
+        // A blocked cell leaves an odd number of cells, and is the floor of its column
+        let layout = "_ _ _ _\n_ _ _ _\n_ _ _ _\n* _ _ _".parse().unwrap();
+        let blocked = Board::new_human_vs_human().with_layout(&layout).unwrap();
+        assert_eq!(blocked.cells_left(), 15);
+        assert_eq!(odd_player(&blocked), Some(PlayerId::Two));
+        let above = Threat {
+            position: Position { row: 2, col: 0 },
+            player: PlayerId::Two,
+        };
+        assert!(above.is_odd(&blocked));
+        assert!(above.has_good_parity(&blocked));
+    }
+
+    #[test]
+    fn test_vertical_and_diagonal_threats() {
+        // X has three stacked in column 0, O three in column 1
+        let board = board_from_moves(&[0, 1, 0, 1, 0, 1]).unwrap();
+        let threats = find_threats(&board);
+
+        assert!(threats.contains(&Threat {
+            position: Position {
+                row: ROWS - 4,
+                col: 0
+            },
+            player: PlayerId::One,
+        }));
+        assert!(threats.contains(&Threat {
+            position: Position {
+                row: ROWS - 4,
+                col: 1
+            },
+            player: PlayerId::Two,
+        }));
+
+        assert!(find_threats(&Board::new_human_vs_human()).is_empty());
+    }
+
+    #[test]
+    fn test_wrapped_threats() {
+        let moves = [6, 6, 0, 0, 1];
+        let wrapped = |board: Board| {
+            find_threats(&board)
+                .iter()
+                .any(|t| t.player == PlayerId::One && t.position.col == 5)
+        };
+
+        assert!(!wrapped(board_from_moves(&moves).unwrap()));
+        let cylinder = board_from_moves(&moves)
+            .unwrap()
+            .with_variant(Variant::Cylinder);
+        assert!(wrapped(cylinder));
+    }
+
+    #[test]
+    fn test_threat_score() {
+        let board = stacked_position();
+        let weights = EvalWeights {
+            mode: EvalMode::Threats,
+            ..EvalWeights::default()
+        };
+
+        // Player 1's stacked even threat against Player 2's playable one
+        let expected = weights.bad_threat - weights.playable_threat;
+        assert_eq!(threat_score(&board, PlayerId::One, &weights), expected);
+        assert_eq!(threat_score(&board, PlayerId::Two, &weights), -expected);
+
+        let player = board.get_current_player();
+        assert_eq!(
+            board.evaluate_with(player, &weights),
+            board.evaluate_with(player, &EvalWeights::default())
+                + threat_score(&board, player.id, &weights)
+        );
+    }
+}
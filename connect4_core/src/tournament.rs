@@ -0,0 +1,429 @@
+use crate::agent::{play_opening, Agent, AgentError, SeededAI};
+use crate::ai::AI;
+use crate::player::PlayerId;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+type AgentFactory = dyn Fn(u64) -> Result<Box<dyn Agent>, AgentError> + Send + Sync;
+
+/**
+ * A tournament participant. Every game gets a fresh agent from `factory`,
+ * given a seed of its own for any random choices, see `game_seed`.
+ */
+pub struct Entrant {
+    pub name: String,
+    factory: Box<AgentFactory>,
+}
+
+impl Entrant {
+    pub fn new(
+        name: impl Into<String>,
+        factory: impl Fn(u64) -> Result<Box<dyn Agent>, AgentError> + Send + Sync + 'static,
+    ) -> Self {
+        Entrant {
+            name: name.into(),
+            factory: Box::new(factory),
+        }
+    }
+
+    pub fn ai(ai: AI) -> Self {
+        Entrant::new(ai.name(), move |seed| {
+            Ok(Box::new(SeededAI::new(ai.clone(), seed)) as Box<dyn Agent>)
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    /// Everyone plays everyone.
+    RoundRobin,
+    /// The first entrant plays everyone else.
+    Gauntlet,
+}
+
+/// Wins, draws and losses from one side's point of view.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Record {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Record {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Fraction of the points scored, a draw being worth half a win. Even before any games.
+    pub fn score(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.5;
+        }
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    /**
+     * Elo difference against the opposition and the half-width of its 95%
+     * confidence interval. The interval stops half a point short of a
+     * perfect or zero score, which would be infinitely far away, and is
+     * unbounded before any games.
+     */
+    pub fn elo(&self) -> (f64, f64) {
+        if self.games() == 0 {
+            return (0.0, f64::INFINITY);
+        }
+        let games = self.games() as f64;
+        let score = self.score();
+        let variance = (self.wins as f64 * (1.0 - score).powi(2)
+            + self.draws as f64 * (0.5 - score).powi(2)
+            + self.losses as f64 * score.powi(2))
+            / games;
+        let margin = 1.96 * (variance / games).sqrt();
+
+        let edge = 0.5 / games;
+        let low = score_to_elo((score - margin).max(edge));
+        let high = score_to_elo((score + margin).min(1.0 - edge));
+        (score_to_elo(score), (high - low) / 2.0)
+    }
+
+    pub fn reversed(&self) -> Record {
+        Record {
+            wins: self.losses,
+            draws: self.draws,
+            losses: self.wins,
+        }
+    }
+
+    pub fn add(&mut self, other: Record) {
+        self.wins += other.wins;
+        self.draws += other.draws;
+        self.losses += other.losses;
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}-{}", self.wins, self.draws, self.losses)
+    }
+}
+
+/**
+ * The seed for `entrant`'s agent in game number `game` of a tournament or
+ * test seeded with `seed`. Every game and every side of it gets different
+ * random choices, and the same seed plays the same games again.
+ */
+pub fn game_seed(seed: u64, game: usize, entrant: usize) -> u64 {
+    let mut rng = StdRng::seed_from_u64(seed.wrapping_add(game as u64));
+    rng.gen::<u64>().wrapping_add(entrant as u64)
+}
+
+/**
+ * Plays one game from `opening` with `one` as Player 1, returning the result
+ * for `one`. The agents are made with `seeds`, in the same order. An entrant
+ * whose agent cannot be created forfeits.
+ */
+pub fn play_pairing(one: &Entrant, two: &Entrant, opening: &[usize], seeds: [u64; 2]) -> Record {
+    let winner = match ((one.factory)(seeds[0]), (two.factory)(seeds[1])) {
+        (Ok(mut one), Ok(mut two)) => play_opening(opening, [one.as_mut(), two.as_mut()]).winner(),
+        (Err(_), Ok(_)) => Some(PlayerId::Two),
+        (Ok(_), Err(_)) => Some(PlayerId::One),
+        (Err(_), Err(_)) => None,
+    };
+
+    match winner {
+        Some(PlayerId::One) => Record {
+            wins: 1,
+            ..Record::default()
+        },
+        Some(_) => Record {
+            losses: 1,
+            ..Record::default()
+        },
+        None => Record {
+            draws: 1,
+            ..Record::default()
+        },
+    }
+}
+
+/// The Elo difference that gives an expected score of `score`. Infinite for 0% and 100%.
+pub fn score_to_elo(score: f64) -> f64 {
+    let score = score.clamp(0.0, 1.0);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+pub struct Tournament {
+    entrants: Vec<Entrant>,
+    format: Format,
+    games_per_pair: usize,
+    seed: u64,
+    threads: usize,
+}
+
+impl Tournament {
+    pub fn new(format: Format) -> Self {
+        Tournament {
+            entrants: Vec::new(),
+            format,
+            games_per_pair: 2,
+            seed: rand::random(),
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+
+    pub fn entrant(mut self, entrant: Entrant) -> Self {
+        self.entrants.push(entrant);
+        self
+    }
+
+    /// Games between each pairing. The first player alternates, so an even number is fairest.
+    pub fn games_per_pair(mut self, games: usize) -> Self {
+        self.games_per_pair = games;
+        self
+    }
+
+    /// Where every agent's random choices are drawn from, see `game_seed`.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    fn pairings(&self) -> Vec<(usize, usize)> {
+        let n = self.entrants.len();
+        match self.format {
+            Format::RoundRobin => (0..n)
+                .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+                .collect(),
+            Format::Gauntlet => (1..n).map(|j| (0, j)).collect(),
+        }
+    }
+
+    /// Plays every game, spread over the configured number of threads.
+    pub fn run(&self) -> Crosstable {
+        let mut games = Vec::new();
+        for (i, j) in self.pairings() {
+            for game in 0..self.games_per_pair {
+                games.push(if game % 2 == 0 { (i, j) } else { (j, i) });
+            }
+        }
+
+        let n = self.entrants.len();
+        let results = Mutex::new(vec![vec![Record::default(); n]; n]);
+        let next_game = AtomicUsize::new(0);
+
+        thread::scope(|scope| {
+            for _ in 0..self.threads.min(games.len()) {
+                scope.spawn(|| loop {
+                    let index = next_game.fetch_add(1, Ordering::Relaxed);
+                    let Some(&(first, second)) = games.get(index) else {
+                        break;
+                    };
+
+                    let seeds = [
+                        game_seed(self.seed, index, first),
+                        game_seed(self.seed, index, second),
+                    ];
+                    let record =
+                        play_pairing(&self.entrants[first], &self.entrants[second], &[], seeds);
+
+                    let mut results = results.lock().unwrap();
+                    results[first][second].add(record);
+                    results[second][first].add(record.reversed());
+                });
+            }
+        });
+
+        Crosstable {
+            names: self.entrants.iter().map(|e| e.name.clone()).collect(),
+            results: results.into_inner().unwrap(),
+        }
+    }
+}
+
+/// Results of a tournament, `results[i][j]` being entrant i's record against entrant j.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Crosstable {
+    pub names: Vec<String>,
+    pub results: Vec<Vec<Record>>,
+}
+
+impl Crosstable {
+    pub fn total(&self, entrant: usize) -> Record {
+        let mut total = Record::default();
+        for record in &self.results[entrant] {
+            total.add(*record);
+        }
+        total
+    }
+}
+
+impl fmt::Display for Crosstable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.names.iter().map(|name| name.len()).max().unwrap_or(0) + 4;
+
+        write!(f, "{:width$}", "")?;
+        for i in 0..self.names.len() {
+            write!(f, " {:>9}", i + 1)?;
+        }
+        writeln!(f, " {:>9} {:>6} {:>14}", "Total", "Score", "Elo")?;
+
+        for (i, name) in self.names.iter().enumerate() {
+            write!(f, "{:width$}", format!("{:>2}. {}", i + 1, name))?;
+            for (j, record) in self.results[i].iter().enumerate() {
+                if i == j || record.games() == 0 {
+                    write!(f, " {:>9}", "-")?;
+                } else {
+                    write!(f, " {:>9}", record.to_string())?;
+                }
+            }
+
+            let total = self.total(i);
+            if total.games() == 0 {
+                writeln!(f)?;
+                continue;
+            }
+            let (elo, margin) = total.elo();
+            // A perfect or zero score has no finite estimate
+            let elo = if elo.is_finite() {
+                format!("{:+.0} ± {:.0}", elo + 0.0, margin)
+            } else {
+                format!("{:+}", elo)
+            };
+            writeln!(
+                f,
+                " {:>9} {:>5.1}% {:>14}",
+                total.to_string(),
+                total.score() * 100.0,
+                elo
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::difficulty::Difficulty;
+
+    #[test]
+    fn test_elo_estimates() {
+        let even = Record {
+            wins: 5,
+            draws: 2,
+            losses: 5,
+        };
+        assert_eq!(even.elo().0, 0.0);
+
+        let strong = Record {
+            wins: 3,
+            draws: 0,
+            losses: 1,
+        };
+        let (elo, margin) = strong.elo();
+        assert!((elo - 190.8).abs() < 0.1);
+        assert!(margin > 0.0);
+
+        let perfect = Record {
+            wins: 4,
+            draws: 0,
+            losses: 0,
+        };
+        assert_eq!(perfect.elo().0, f64::INFINITY);
+
+        // 5-0-1 is a long way from even, but the interval still has an end
+        let lopsided = Record {
+            wins: 5,
+            draws: 0,
+            losses: 1,
+        };
+        let (elo, margin) = lopsided.elo();
+        assert!(elo.is_finite() && margin.is_finite());
+
+        assert_eq!(Record::default().score(), 0.5);
+        assert_eq!(Record::default().elo(), (0.0, f64::INFINITY));
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let crosstable = Tournament::new(Format::RoundRobin)
+            .entrant(Entrant::ai(AI {
+                depth: 1,
+                ..AI::default()
+            }))
+            .entrant(Entrant::ai(AI {
+                depth: 2,
+                ..AI::default()
+            }))
+            .entrant(Entrant::ai(AI {
+                depth: 3,
+                ..AI::default()
+            }))
+            .games_per_pair(2)
+            .threads(2)
+            .run();
+
+        for i in 0..3 {
+            assert_eq!(crosstable.total(i).games(), 4);
+            for j in 0..3 {
+                assert_eq!(
+                    crosstable.results[i][j],
+                    crosstable.results[j][i].reversed()
+                );
+            }
+        }
+        assert!(crosstable.to_string().contains("AI (depth 3)"));
+    }
+
+    #[test]
+    fn test_seed_replays_tournament() {
+        let run = |seed| {
+            Tournament::new(Format::RoundRobin)
+                .entrant(Entrant::ai(Difficulty::Beginner.ai()))
+                .entrant(Entrant::ai(Difficulty::Easy.ai()))
+                .games_per_pair(4)
+                .threads(2)
+                .seed(seed)
+                .run()
+        };
+        assert_eq!(run(5), run(5));
+
+        assert_ne!(game_seed(5, 0, 0), game_seed(5, 0, 1));
+        assert_ne!(game_seed(5, 0, 0), game_seed(5, 1, 0));
+    }
+
+    #[test]
+    fn test_gauntlet_and_forfeits() {
+        let crosstable = Tournament::new(Format::Gauntlet)
+            .entrant(Entrant::ai(AI {
+                depth: 1,
+                ..AI::default()
+            }))
+            .entrant(Entrant::ai(AI {
+                depth: 1,
+                ..AI::default()
+            }))
+            .entrant(Entrant::new("Broken", |_| Err(AgentError::NoMove)))
+            .games_per_pair(2)
+            .run();
+
+        assert_eq!(crosstable.results[1][2].games(), 0);
+        assert_eq!(
+            crosstable.results[0][2],
+            Record {
+                wins: 2,
+                draws: 0,
+                losses: 0,
+            }
+        );
+    }
+}
@@ -0,0 +1,271 @@
+use crate::ai::AI;
+use crate::eval::EvalWeights;
+use crate::format::FormatError;
+use crate::game::{Board, GameState, COLS};
+use crate::protocol::{board_from_moves, format_moves, parse_moves};
+use rand::Rng;
+use std::{fs, io};
+
+/// A position from a finished game, with how the game went for the player to move.
+#[derive(Clone, PartialEq, Debug)]
+pub struct TrainingPosition {
+    pub moves: Vec<usize>,
+    pub board: Board,
+    /// 1 for a win, 0.5 for a draw and 0 for a loss.
+    pub result: f64,
+}
+
+/**
+ * Plays `games` games of `ai` against itself and returns every position from
+ * them that was not already over. The first `random_plies` moves of each game
+ * are random, otherwise every game would be the same.
+ */
+pub fn self_play_positions(
+    games: usize,
+    ai: AI,
+    random_plies: usize,
+    rng: &mut impl Rng,
+) -> Vec<TrainingPosition> {
+    let mut positions = Vec::new();
+
+    for _ in 0..games {
+        let mut board = Board::new_human_vs_human();
+        let mut moves = Vec::new();
+        let mut seen = Vec::new();
+
+        while *board.get_game_state() == GameState::InProgress {
+            let col = if moves.len() < random_plies {
+                let legal: Vec<usize> = (0..COLS).filter(|&col| board.valid_move(col)).collect();
+                legal[rng.gen_range(0..legal.len())]
+            } else {
+                seen.push((moves.clone(), board.clone()));
+                ai.best_move(&board).expect("the game is not over")
+            };
+            board.play_move(col);
+            moves.push(col);
+        }
+
+        let winner = match board.get_game_state() {
+            GameState::Win(id) => Some(id),
+            _ => None,
+        };
+        for (moves, board) in seen {
+            let result = match winner {
+                None => 0.5,
+                Some(id) if *id == board.get_current_player().id => 1.0,
+                Some(_) => 0.0,
+            };
+            positions.push(TrainingPosition {
+                moves,
+                board,
+                result,
+            });
+        }
+    }
+
+    positions
+}
+
+/// Positions are saved one per line as the columns played and the result.
+pub fn save_positions(positions: &[TrainingPosition], path: &str) -> io::Result<()> {
+    let mut text = String::new();
+    for position in positions {
+        let moves = if position.moves.is_empty() {
+            "-".to_string()
+        } else {
+            format_moves(&position.moves)
+        };
+        text.push_str(&format!("{} {}\n", moves, position.result));
+    }
+    fs::write(path, text)
+}
+
+pub fn load_positions(path: &str) -> io::Result<Vec<TrainingPosition>> {
+    let mut positions = Vec::new();
+
+    for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let error = |message: String| FormatError::at(number, message);
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some((moves, result)) = line.split_once(' ') else {
+            return Err(error(format!("expected moves and a result: {}", line)).into());
+        };
+        let moves = if moves == "-" {
+            Vec::new()
+        } else {
+            parse_moves(moves).map_err(|err| error(err.0))?
+        };
+        positions.push(TrainingPosition {
+            board: board_from_moves(&moves).map_err(|err| error(err.0))?,
+            moves,
+            result: result
+                .trim()
+                .parse()
+                .map_err(|_| error(format!("invalid result: {}", result)))?,
+        });
+    }
+
+    Ok(positions)
+}
+
+/**
+ * Texel-style tuning: the evaluation of each position, squashed by a
+ * logistic curve, predicts the result, and the weights are nudged one at a
+ * time for as long as that lowers the mean squared prediction error.
+ */
+pub struct Tuner {
+    positions: Vec<TrainingPosition>,
+    /// Spread of the logistic curve: an evaluation of `scale` predicts a 73% score.
+    pub scale: f64,
+}
+
+impl Tuner {
+    pub fn new(positions: Vec<TrainingPosition>) -> Self {
+        Tuner {
+            positions,
+            scale: 1000.0,
+        }
+    }
+
+    fn predict(&self, board: &Board, weights: &EvalWeights) -> f64 {
+        let eval = board.evaluate_for_with(board.get_current_player().id, weights) as f64;
+        1.0 / (1.0 + (-eval / self.scale).exp())
+    }
+
+    /// Mean squared difference between predicted and actual results.
+    pub fn error(&self, weights: &EvalWeights) -> f64 {
+        let total: f64 = self
+            .positions
+            .iter()
+            .map(|position| (position.result - self.predict(&position.board, weights)).powi(2))
+            .sum();
+        total / self.positions.len().max(1) as f64
+    }
+
+    /// Picks the `scale` that best fits `weights` before tuning them.
+    pub fn fit_scale(&mut self, weights: &EvalWeights) {
+        // The error is unimodal in the scale, so narrow it down on a log scale
+        let (mut low, mut high) = (1f64.ln(), 1_000_000f64.ln());
+        for _ in 0..60 {
+            let a = low + (high - low) / 3.0;
+            let b = high - (high - low) / 3.0;
+            self.scale = a.exp();
+            let error_a = self.error(weights);
+            self.scale = b.exp();
+            let error_b = self.error(weights);
+            if error_a < error_b {
+                high = b;
+            } else {
+                low = a;
+            }
+        }
+        self.scale = ((low + high) / 2.0).exp();
+    }
+
+    /**
+     * Local search from `start`. Each pass tries moving every weight up and
+     * down by a step proportional to its size; when a pass improves nothing
+     * the steps are halved. `on_pass` gets the pass number and the error.
+     */
+    pub fn tune(
+        &self,
+        start: EvalWeights,
+        passes: usize,
+        mut on_pass: impl FnMut(usize, f64),
+    ) -> EvalWeights {
+        let mut best = start;
+        let mut best_error = self.error(&best);
+        let mut fraction = 0.25;
+
+        for pass in 1..=passes {
+            let mut improved = false;
+
+            for index in 0..EvalWeights::PARAMS {
+                let value = *best.param_mut(index);
+                let step = ((value.abs().max(100) as f64 * fraction) as i32).max(1);
+
+                for candidate in [value + step, value - step] {
+                    let mut weights = best;
+                    *weights.param_mut(index) = candidate;
+                    let error = self.error(&weights);
+                    if error < best_error {
+                        best = weights;
+                        best_error = error;
+                        improved = true;
+                        break;
+                    }
+                }
+            }
+
+            on_pass(pass, best_error);
+            if !improved {
+                fraction /= 2.0;
+                if fraction < 0.001 {
+                    break;
+                }
+            }
+        }
+
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn positions() -> Vec<TrainingPosition> {
+        let mut rng = StdRng::seed_from_u64(7);
+        let ai = AI {
+            depth: 2,
+            ..AI::default()
+        };
+        self_play_positions(6, ai, 4, &mut rng)
+    }
+
+    #[test]
+    fn test_self_play_positions() {
+        let positions = positions();
+
+        assert!(!positions.is_empty());
+        for position in &positions {
+            assert!(position.moves.len() >= 4);
+            assert_eq!(*position.board.get_game_state(), GameState::InProgress);
+            assert!([0.0, 0.5, 1.0].contains(&position.result));
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let positions = positions();
+        let path = std::env::temp_dir().join("connect4_tune_test.txt");
+        let path = path.to_str().unwrap();
+
+        save_positions(&positions, path).unwrap();
+        assert_eq!(load_positions(path).unwrap(), positions);
+
+        // Lines that cannot be read are reported rather than skipped
+        fs::write(path, "33 1\n3344\n").unwrap();
+        let err = load_positions(path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("line 2:"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_tuning_lowers_error() {
+        let mut tuner = Tuner::new(positions());
+        let start = EvalWeights::default();
+        tuner.fit_scale(&start);
+        let start_error = tuner.error(&start);
+
+        let mut errors = Vec::new();
+        let tuned = tuner.tune(start, 5, |_, error| errors.push(error));
+
+        assert!(tuner.error(&tuned) <= start_error);
+        assert!(errors.windows(2).all(|pair| pair[1] <= pair[0]));
+    }
+}
@@ -0,0 +1,186 @@
+use connect4::ai::{SearchLimits, AI};
+use connect4::eval::EvalWeights;
+use connect4::game::Board;
+use connect4::protocol::{board_from_moves, Command, Response};
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::{env, process};
+
+/// A search running in the background, so `stop` can still be read from stdin.
+struct Search {
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+/// Writes one response line, shared between the input loop and the search thread.
+fn send<W: Write>(output: &Mutex<W>, response: Response) {
+    let mut output = output.lock().expect("output lock poisoned");
+    // Nobody is left to tell if the GUI has gone away
+    let _ = writeln!(output, "{}", response).and_then(|_| output.flush());
+}
+
+impl Search {
+    fn start<W: Write + Send + 'static>(
+        ai: AI,
+        board: Board,
+        limits: SearchLimits,
+        output: Arc<Mutex<W>>,
+    ) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&stop);
+
+        let handle = thread::spawn(move || {
+            let result = ai.search(&board, limits, &flag, |info| {
+                send(&output, Response::Info(info.clone()));
+            });
+            send(&output, Response::BestMove(result.best_move));
+        });
+
+        Search { stop, handle }
+    }
+
+    /// Stops the search; it still answers with `bestmove` before returning.
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.wait();
+    }
+
+    fn wait(self) {
+        self.handle.join().expect("search thread panicked");
+    }
+}
+
+fn main() {
+    let mut ai = AI::new(2);
+
+    // connect4_engine [--weights FILE] [--threads N]
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        match (flag.as_str(), args.next()) {
+            ("--weights", Some(path)) => match EvalWeights::load(path) {
+                Ok(weights) => ai.weights = weights,
+                Err(err) => {
+                    eprintln!("error: could not load weights from {}: {}", path, err);
+                    process::exit(2);
+                }
+            },
+            ("--threads", Some(threads)) => match threads.parse() {
+                Ok(threads) => ai.threads = threads,
+                Err(_) => {
+                    eprintln!("error: --threads needs a number");
+                    process::exit(2);
+                }
+            },
+            _ => {
+                eprintln!("usage: connect4_engine [--weights FILE] [--threads N]");
+                process::exit(2);
+            }
+        }
+    }
+
+    run(ai, io::stdin().lock(), Arc::new(Mutex::new(io::stdout())));
+}
+
+/// Answers the commands read from `input` until `quit` or the end of the input.
+fn run<W: Write + Send + 'static>(ai: AI, input: impl BufRead, output: Arc<Mutex<W>>) {
+    let mut board = Board::new_human_vs_human();
+    let mut search: Option<Search> = None;
+
+    for line in input.lines() {
+        let Ok(line) = line else { break };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let command = match line.parse::<Command>() {
+            Ok(command) => command,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                continue;
+            }
+        };
+
+        match command {
+            Command::IsReady => send(&output, Response::ReadyOk),
+            Command::Position(moves) => {
+                if let Some(search) = search.take() {
+                    search.wait();
+                }
+                match board_from_moves(&moves) {
+                    Ok(new_board) => board = new_board,
+                    Err(err) => eprintln!("error: {}", err),
+                }
+            }
+            Command::Go(limits) => {
+                if let Some(search) = search.take() {
+                    search.wait();
+                }
+                let output = Arc::clone(&output);
+                search = Some(Search::start(ai.clone(), board.clone(), limits, output));
+            }
+            Command::Stop => {
+                if let Some(search) = search.take() {
+                    search.stop();
+                }
+            }
+            Command::Quit => {
+                if let Some(search) = search.take() {
+                    search.stop();
+                }
+                return;
+            }
+        }
+    }
+
+    // End of input: let the last search answer
+    if let Some(search) = search.take() {
+        search.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use connect4::game::Play;
+
+    /// Runs the engine on `input` and returns what it answered, one response per line.
+    fn answers(input: &str) -> Vec<Response> {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        run(AI::new(1), input.as_bytes(), Arc::clone(&output));
+        let output = output.lock().unwrap();
+        String::from_utf8_lossy(&output)
+            .lines()
+            .map(|line| line.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_go_answers_bestmove() {
+        let responses = answers("isready\nposition moves 001122\ngo depth 2\n");
+
+        assert_eq!(responses[0], Response::ReadyOk);
+        assert!(matches!(responses[1], Response::Info(ref info) if info.depth == 1));
+        // Player 1 finishes the row
+        assert_eq!(
+            responses.last(),
+            Some(&Response::BestMove(Some(Play::drop(3))))
+        );
+    }
+
+    #[test]
+    fn test_bad_position_keeps_old_board() {
+        // The game is over after 0101010, so the position is rejected and
+        // the search runs on the empty board
+        let responses = answers("position moves 01010101\ngo depth 1\nquit\n");
+        assert!(matches!(
+            responses.last(),
+            Some(Response::BestMove(Some(_)))
+        ));
+
+        let responses = answers("position moves 0101010\ngo depth 1\n");
+        assert_eq!(responses.last(), Some(&Response::BestMove(None)));
+    }
+}